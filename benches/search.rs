@@ -0,0 +1,108 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use exek::config::SearchConfig;
+use exek::database::Database;
+use exek::desktop_entry::AppEntry;
+use exek::search::Searcher;
+use std::path::PathBuf;
+
+const WORDS: &[&str] = &[
+    "audio", "browser", "calc", "desktop", "editor", "files", "graphics", "image", "libre", "mail",
+    "manager", "media", "monitor", "network", "office", "player", "settings", "system", "terminal",
+    "text", "viewer", "video", "web", "writer",
+];
+
+/// Generates `count` entries with names of three pseudo-random words, about
+/// the size of a dmenu list piped from a large file.
+fn entries(count: usize) -> Vec<AppEntry> {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut word = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        WORDS[(seed % WORDS.len() as u64) as usize]
+    };
+
+    (0..count)
+        .map(|i| {
+            let name = format!("{} {} {}", word(), word(), word());
+            AppEntry {
+                id: format!("entry-{}.desktop", i),
+                exec: format!("{}-{}", word(), i),
+                icon: None,
+                comment: Some(format!("{} {}", word(), word())),
+                categories: vec![word().to_string()],
+                keywords: Vec::new(),
+                mime_types: Vec::new(),
+                desktop_file: PathBuf::from(format!("/usr/share/applications/entry-{}.desktop", i)),
+                terminal: false,
+                no_display: false,
+                name,
+            }
+        })
+        .collect()
+}
+
+fn searcher(count: usize) -> Searcher {
+    Searcher::new(entries(count), Database::new(), &SearchConfig::default())
+}
+
+/// A single query against every entry, on one thread and on all cores.
+fn full_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_scan");
+    group.sample_size(20);
+
+    for count in [1_000, 100_000] {
+        let mut searcher = searcher(count);
+        let single_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+        group.bench_with_input(BenchmarkId::new("sequential", count), &count, |b, _| {
+            b.iter(|| {
+                single_thread.install(|| {
+                    searcher.reset();
+                    black_box(searcher.search("libwri"))
+                })
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", count), &count, |b, _| {
+            b.iter(|| {
+                searcher.reset();
+                black_box(searcher.search("libwri"))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Typing a query one keystroke at a time, rescanning every entry on each
+/// keystroke versus only rescoring the previous matches.
+fn typing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("typing");
+    group.sample_size(20);
+
+    let query = "libre writer";
+    let prefixes: Vec<&str> = (1..=query.len()).map(|end| &query[..end]).collect();
+    let mut searcher = searcher(100_000);
+
+    group.bench_function("rescan", |b| {
+        b.iter(|| {
+            for prefix in &prefixes {
+                searcher.reset();
+                black_box(searcher.search(prefix));
+            }
+        })
+    });
+    group.bench_function("incremental", |b| {
+        b.iter(|| {
+            searcher.reset();
+            for prefix in &prefixes {
+                black_box(searcher.search(prefix));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, full_scan, typing);
+criterion_main!(benches);
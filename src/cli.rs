@@ -0,0 +1,151 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// A fast TUI application launcher with fuzzy matching and frecency-based sorting
+#[derive(Debug, Parser)]
+#[command(name = "exek", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Show how each result's score is made up (toggle with Alt+e)
+    #[arg(long)]
+    pub explain: bool,
+    /// List the actions keys can be bound to and their current bindings
+    #[arg(long)]
+    pub list_actions: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Inspect and edit the launch history database
+    #[command(subcommand)]
+    History(HistoryCommand),
+    /// Always list an entry first when the query is empty; lists pins without an ID
+    Pin(EntryArgs),
+    /// Remove an entry from the pinned list
+    Unpin(EntryArgs),
+    /// Never show an entry in results; lists hidden entries without an ID
+    Hide(EntryArgs),
+    /// Show a hidden entry again
+    Unhide(EntryArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct EntryArgs {
+    /// Desktop file ID (e.g. `firefox.desktop`) or `path:<path>` key
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// List all recorded entries, most recently launched first
+    List(OutputArgs),
+    /// Show the highest ranked entries by frecency
+    Top {
+        /// Number of entries to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Remove a single entry by its ID
+    Remove {
+        /// Desktop file ID (e.g. `firefox.desktop`) or `path:<path>` key
+        id: String,
+    },
+    /// Remove all recorded entries
+    Reset,
+    /// Remove old or stale entries
+    #[command(arg_required_else_help = true)]
+    Prune {
+        /// Remove entries not launched within this long (e.g. `90d`, `12w`, `48h`)
+        #[arg(long, value_parser = parse_age)]
+        older_than: Option<chrono::Duration>,
+        /// Remove `path:` entries whose file no longer exists
+        #[arg(long)]
+        missing: bool,
+    },
+    /// Write the database as JSON to a file or stdout
+    Export {
+        /// Output file; stdout if omitted
+        file: Option<PathBuf>,
+    },
+    /// Merge a previously exported database into the current one
+    Import {
+        /// Input file; `-` reads from stdin
+        file: PathBuf,
+        /// Replace the current history instead of merging into it
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Import launch counts from another launcher's history cache
+    ImportFrom {
+        /// Launcher whose history to read
+        launcher: Launcher,
+        /// Read this file instead of the launcher's default cache files
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Show what would be imported without changing the database
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Launcher {
+    Rofi,
+    Wofi,
+    Fuzzel,
+    Dmenu,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Parses ages like `90d`, `12w` or `48h`. A bare number is taken as days.
+fn parse_age(value: &str) -> Result<chrono::Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => value.split_at(pos),
+        None => (value, "d"),
+    };
+
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("invalid age '{}'", value))?;
+
+    let age = match unit {
+        "h" => chrono::TimeDelta::try_hours(number),
+        "d" => chrono::TimeDelta::try_days(number),
+        "w" => chrono::TimeDelta::try_weeks(number),
+        _ => return Err(format!("unknown unit '{}' (expected h, d or w)", unit)),
+    };
+    age.ok_or_else(|| "age out of range".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("48h"), Ok(Duration::hours(48)));
+        assert_eq!(parse_age("90d"), Ok(Duration::days(90)));
+        assert_eq!(parse_age(" 12w "), Ok(Duration::weeks(12)));
+        assert_eq!(parse_age("30"), Ok(Duration::days(30)));
+
+        assert!(parse_age("").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("-5d").is_err());
+        assert!(parse_age("10m").is_err());
+        assert!(parse_age("10dd").is_err());
+        assert_eq!(parse_age("1000000000000d"), Err("age out of range".to_string()));
+        // In range, though too long to subtract from the current time
+        assert_eq!(parse_age("1000000000d"), Ok(Duration::days(1_000_000_000)));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::desktop_entry::AppEntry;
+use crate::matcher::{CaseMode, MatcherKind};
+use crate::path_completion::PathFilter;
+use crate::search::EmptyView;
+use anyhow::{Context, Result};
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// A launcher entry defined in the config file rather than a desktop file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomEntry {
+    pub name: String,
+    /// Program and arguments, executed without a shell
+    pub argv: Vec<String>,
+    #[serde(default)]
+    pub terminal: bool,
+    pub icon: Option<String>,
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+/// A text style in the config file, e.g. `{ fg = "green", bold = true }`.
+/// Colors are names (`red`, `lightblue`), indexed (`208`) or hex (`#ff8800`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl StyleConfig {
+    pub fn to_style(&self) -> Style {
+        let mut style = Style::default();
+        if let Some(fg) = self.fg {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = self.bg {
+            style = style.bg(bg);
+        }
+        if self.bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if self.italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        if self.underline {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        style
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Style of the characters a query matched, layered over the row style
+    pub match_highlight: StyleConfig,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            match_highlight: StyleConfig {
+                fg: Some(Color::LightGreen),
+                bold: true,
+                ..StyleConfig::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Algorithm for plain query terms; can be cycled at runtime
+    pub matcher: MatcherKind,
+    /// `smart`, `ignore` or `respect`
+    pub case: CaseMode,
+    /// Ignore diacritics and match ligatures by their spelled-out letters
+    pub normalize: bool,
+    /// Add approximate matches for typos when a query has fewer results than
+    /// this; 0 disables the fallback
+    pub typo_fallback_threshold: usize,
+    /// Maximum number of results for a query; 0 means no limit
+    pub max_results: usize,
+    pub empty_query: EmptyQueryConfig,
+    pub ranking: RankingConfig,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            matcher: MatcherKind::default(),
+            case: CaseMode::default(),
+            normalize: true,
+            typo_fallback_threshold: 3,
+            max_results: 0,
+            empty_query: EmptyQueryConfig::default(),
+            ranking: RankingConfig::default(),
+        }
+    }
+}
+
+/// What is listed before anything is typed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmptyQueryConfig {
+    pub view: EmptyView,
+    /// Maximum number of entries listed; defaults to 20 for the `recent`
+    /// view and no limit for the others, and 0 means no limit
+    pub limit: Option<usize>,
+}
+
+/// Weights of the ranking formula. A result's score is the sum over query
+/// groups of the best field match multiplied by the field's weight, plus
+/// the boosts and a share of the entry's frecency.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RankingConfig {
+    /// Field weights; 0 stops a field from matching plain terms
+    pub name: f64,
+    pub exec: f64,
+    pub comment: f64,
+    pub keyword: f64,
+    pub category: f64,
+    /// Added when the best match is in the name
+    pub name_boost: i64,
+    /// Added to pinned entries
+    pub pin_boost: i64,
+    /// Share of the frecency added to the score
+    pub frecency_weight: f64,
+    /// Frecency above this counts as this much
+    pub frecency_cap: f64,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            name: 1.0,
+            exec: 1.0,
+            comment: 0.5,
+            keyword: 0.5,
+            category: 1.0 / 3.0,
+            name_boost: 10,
+            pin_boost: 20,
+            frecency_weight: 0.1,
+            frecency_cap: 100.0,
+        }
+    }
+}
+
+/// What path mode lists; the filter and hidden entries can be toggled at
+/// runtime.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// `all`, `executables` or `directories`
+    pub show: PathFilter,
+    /// List hidden entries even if the typed name doesn't start with a dot
+    pub hidden: bool,
+    /// Leave out entries matched by `.gitignore` and `.ignore` files
+    pub ignore_files: bool,
+}
+
+/// Vi-style modal editing: Esc switches to normal mode instead of quitting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViConfig {
+    pub enabled: bool,
+    /// Normal mode key bindings on top of the defaults, like `[keys]`
+    pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Custom entries, listed as `[[entries]]` tables
+    pub entries: Vec<CustomEntry>,
+    pub search: SearchConfig,
+    pub paths: PathsConfig,
+    pub theme: Theme,
+    /// Key bindings on top of the defaults, e.g. `"ctrl-x ctrl-c" = "exit"`;
+    /// `"none"` removes a default binding
+    pub keys: BTreeMap<String, String>,
+    pub vi: ViConfig,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let path = Self::config_path()?;
+        if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            toml::from_str(&data).with_context(|| format!("Invalid config file {}", path.display()))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Returns the custom entries as [`AppEntry`]s, skipping entries with
+    /// an empty `argv`.
+    pub fn custom_apps(&self) -> Vec<AppEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.argv.is_empty())
+            .map(AppEntry::from_custom)
+            .collect()
+    }
+
+    fn config_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        Ok(config_dir.join("exek").join("config.toml"))
+    }
+}
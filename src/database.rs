@@ -1,34 +1,90 @@
+use crate::desktop_entry::AppEntry;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Schema version written by this build. Version 0 databases keyed desktop
+/// applications by display name instead of desktop file ID.
+const CURRENT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppUsage {
     pub launch_count: u32,
     pub last_launched: Option<DateTime<Utc>>,
 }
 
-impl Default for AppUsage {
-    fn default() -> Self {
-        Self {
-            launch_count: 0,
-            last_launched: None,
+impl AppUsage {
+    /// Launch count weighted by how recently the entry was last launched.
+    pub fn frecency(&self) -> f64 {
+        let base_score = self.launch_count as f64;
+
+        if let Some(last_launched) = self.last_launched {
+            let now = Utc::now();
+            let days_ago = (now - last_launched).num_days() as f64;
+
+            let recency_multiplier = if days_ago < 1.0 {
+                2.0
+            } else if days_ago < 7.0 {
+                1.5
+            } else if days_ago < 30.0 {
+                1.0
+            } else if days_ago < 90.0 {
+                0.5
+            } else {
+                0.25
+            };
+
+            base_score * recency_multiplier
+        } else {
+            0.0
         }
     }
+
+    fn record(&mut self) {
+        self.launch_count += 1;
+        self.last_launched = Some(Utc::now());
+    }
+
+    fn merge(&mut self, other: &AppUsage) {
+        self.launch_count += other.launch_count;
+        self.last_launched = self.last_launched.max(other.last_launched);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Database {
+    #[serde(default)]
+    pub version: u32,
     pub usage: HashMap<String, AppUsage>,
+    /// Entries always listed first, in the order they were pinned
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    /// Entries never shown in results
+    #[serde(default)]
+    pub hidden: BTreeSet<String>,
+    /// Files opened per type and application, to rank the applications
+    /// offered by "open with"
+    #[serde(default)]
+    pub handlers: HashMap<String, HashMap<String, AppUsage>>,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Database {
     pub fn new() -> Self {
         Self {
+            version: CURRENT_VERSION,
             usage: HashMap::new(),
+            pinned: Vec::new(),
+            hidden: BTreeSet::new(),
+            handlers: HashMap::new(),
         }
     }
 
@@ -52,53 +108,177 @@ impl Database {
         Ok(())
     }
 
-    pub fn record_launch(&mut self, app_name: &str) -> Result<()> {
-        let usage = self.usage.entry(app_name.to_string()).or_default();
-        usage.launch_count += 1;
-        usage.last_launched = Some(Utc::now());
+    /// Moves usage recorded under display names (schema version 0) to the
+    /// desktop file IDs of the given entries. Names that match no entry, or
+    /// more than one, are left untouched. Returns whether anything changed.
+    pub fn migrate(&mut self, apps: &[AppEntry]) -> bool {
+        if self.version >= CURRENT_VERSION {
+            return false;
+        }
+
+        let legacy_keys: Vec<String> = self
+            .usage
+            .keys()
+            .filter(|key| !key.starts_with("path:"))
+            .filter(|key| !apps.iter().any(|app| &app.id == *key))
+            .cloned()
+            .collect();
+
+        for key in legacy_keys {
+            let mut matches = apps.iter().filter(|app| app.name == key);
+            if let (Some(app), None) = (matches.next(), matches.next()) {
+                if let Some(old) = self.usage.remove(&key) {
+                    self.usage.entry(app.id.clone()).or_default().merge(&old);
+                }
+            }
+        }
+
+        self.version = CURRENT_VERSION;
+        true
+    }
+
+    pub fn record_launch(&mut self, app_id: &str) -> Result<()> {
+        self.usage.entry(app_id.to_string()).or_default().record();
         self.save()
     }
 
     pub fn record_path_launch(&mut self, path: &std::path::Path) -> Result<()> {
         // Store with a special prefix to distinguish path-based launches
         let key = format!("path:{}", path.display());
-        let usage = self.usage.entry(key).or_default();
-        usage.launch_count += 1;
-        usage.last_launched = Some(Utc::now());
+        self.usage.entry(key).or_default().record();
         self.save()
     }
 
-    pub fn get_usage(&self, app_name: &str) -> AppUsage {
-        self.usage
-            .get(app_name)
-            .cloned()
-            .unwrap_or_default()
+    /// Removes the entry's launch history along with its usage for
+    /// opening files.
+    pub fn remove(&mut self, app_id: &str) -> Option<AppUsage> {
+        self.remove_handler(app_id);
+        self.usage.remove(app_id)
     }
 
-    pub fn calculate_frecency(&self, app_name: &str) -> f64 {
-        let usage = self.get_usage(app_name);
-        let base_score = usage.launch_count as f64;
+    fn remove_handler(&mut self, app_id: &str) {
+        self.handlers.retain(|_, usage| {
+            usage.remove(app_id);
+            !usage.is_empty()
+        });
+    }
 
-        if let Some(last_launched) = usage.last_launched {
-            let now = Utc::now();
-            let days_ago = (now - last_launched).num_days() as f64;
+    pub fn reset(&mut self) {
+        self.usage.clear();
+        self.handlers.clear();
+    }
 
-            let recency_multiplier = if days_ago < 1.0 {
-                2.0
-            } else if days_ago < 7.0 {
-                1.5
-            } else if days_ago < 30.0 {
-                1.0
-            } else if days_ago < 90.0 {
-                0.5
+    /// Removes every entry for which `should_remove` returns true, along
+    /// with its usage for opening files, and returns the removed IDs. Usage
+    /// for opening files of one type is also removed on its own when
+    /// `should_remove` returns true for it.
+    pub fn prune<F>(&mut self, mut should_remove: F) -> Vec<String>
+    where
+        F: FnMut(&str, &AppUsage) -> bool,
+    {
+        let mut removed = Vec::new();
+        self.usage.retain(|key, usage| {
+            if should_remove(key, usage) {
+                removed.push(key.clone());
+                false
             } else {
-                0.25
-            };
+                true
+            }
+        });
+        for key in &removed {
+            self.remove_handler(key);
+        }
+        self.handlers.retain(|_, usage| {
+            usage.retain(|key, usage| !should_remove(key, usage));
+            !usage.is_empty()
+        });
+        removed.sort();
+        removed
+    }
 
-            base_score * recency_multiplier
+    /// Adds the launch counts of `other` to this database, keeping the most
+    /// recent launch time for entries present in both. Usage for opening
+    /// files is merged per type the same way.
+    pub fn merge(&mut self, other: &Database) {
+        for (key, usage) in &other.usage {
+            self.merge_usage(key, usage);
+        }
+        for (mime, usage) in &other.handlers {
+            let handlers = self.handlers.entry(mime.clone()).or_default();
+            for (key, usage) in usage {
+                handlers.entry(key.clone()).or_default().merge(usage);
+            }
+        }
+    }
+
+    pub fn merge_usage(&mut self, app_id: &str, usage: &AppUsage) {
+        self.usage.entry(app_id.to_string()).or_default().merge(usage);
+    }
+
+    pub fn is_pinned(&self, app_id: &str) -> bool {
+        self.pinned.iter().any(|id| id == app_id)
+    }
+
+    /// Returns the position of a pinned entry, used to keep pins in order.
+    pub fn pin_position(&self, app_id: &str) -> Option<usize> {
+        self.pinned.iter().position(|id| id == app_id)
+    }
+
+    /// Pins or unpins an entry. Returns whether anything changed.
+    pub fn set_pinned(&mut self, app_id: &str, pinned: bool) -> bool {
+        if pinned == self.is_pinned(app_id) {
+            return false;
+        }
+        if pinned {
+            self.pinned.push(app_id.to_string());
         } else {
-            0.0
+            self.pinned.retain(|id| id != app_id);
         }
+        true
+    }
+
+    pub fn is_hidden(&self, app_id: &str) -> bool {
+        self.hidden.contains(app_id)
+    }
+
+    /// Hides or unhides an entry. Returns whether anything changed.
+    pub fn set_hidden(&mut self, app_id: &str, hidden: bool) -> bool {
+        if hidden {
+            self.hidden.insert(app_id.to_string())
+        } else {
+            self.hidden.remove(app_id)
+        }
+    }
+
+    pub fn get_usage(&self, app_id: &str) -> AppUsage {
+        self.usage
+            .get(app_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn calculate_frecency(&self, app_id: &str) -> f64 {
+        self.get_usage(app_id).frecency()
+    }
+
+    /// Records that a file of type `mime` was opened with `app_id`.
+    pub fn record_handler_launch(&mut self, mime: &str, app_id: &str) -> Result<()> {
+        self.handlers
+            .entry(mime.to_string())
+            .or_default()
+            .entry(app_id.to_string())
+            .or_default()
+            .record();
+        self.save()
+    }
+
+    /// How often and how recently files of type `mime` were opened with
+    /// `app_id`.
+    pub fn handler_frecency(&self, mime: &str, app_id: &str) -> f64 {
+        self.handlers
+            .get(mime)
+            .and_then(|apps| apps.get(app_id))
+            .map_or(0.0, AppUsage::frecency)
     }
 
     pub fn get_frequent_paths(&self) -> Vec<(String, AppUsage)> {
@@ -115,3 +295,60 @@ impl Database {
         Ok(config_dir.join("exek").join("database.json"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(launch_count: u32) -> AppUsage {
+        AppUsage { launch_count, last_launched: Some(Utc::now()) }
+    }
+
+    #[test]
+    fn migrates_names_to_desktop_file_ids() {
+        let apps = [
+            AppEntry::for_test("org.mozilla.firefox.desktop", "Firefox"),
+            AppEntry::for_test("org.gnome.Nautilus.desktop", "Files"),
+            AppEntry::for_test("thunar.desktop", "Files"),
+        ];
+        let mut database = Database::new();
+        database.version = 0;
+        for (key, count) in [("Firefox", 3), ("org.mozilla.firefox.desktop", 1), ("Files", 2), ("Gone", 4), ("path:/usr/bin/htop", 5)] {
+            database.usage.insert(key.to_string(), usage(count));
+        }
+
+        assert!(database.migrate(&apps));
+        assert_eq!(database.version, CURRENT_VERSION);
+        assert!(!database.usage.contains_key("Firefox"));
+        assert_eq!(database.get_usage("org.mozilla.firefox.desktop").launch_count, 4);
+        // Ambiguous, unknown and path keys stay as they are
+        assert_eq!(database.get_usage("Files").launch_count, 2);
+        assert_eq!(database.get_usage("Gone").launch_count, 4);
+        assert_eq!(database.get_usage("path:/usr/bin/htop").launch_count, 5);
+
+        assert!(!database.migrate(&apps));
+    }
+
+    #[test]
+    fn handler_usage_survives_export_and_follows_its_app() {
+        let mut exported = Database::new();
+        exported.usage.insert("gimp.desktop".to_string(), usage(1));
+        exported.handlers.entry("image/png".to_string()).or_default().insert("gimp.desktop".to_string(), usage(2));
+        exported.handlers.entry("image/png".to_string()).or_default().insert("krita.desktop".to_string(), usage(1));
+
+        let mut database = Database::new();
+        database.handlers.entry("image/png".to_string()).or_default().insert("gimp.desktop".to_string(), usage(1));
+        let imported: Database = serde_json::from_str(&serde_json::to_string(&exported).unwrap()).unwrap();
+        database.merge(&imported);
+        assert_eq!(database.handlers["image/png"]["gimp.desktop"].launch_count, 3);
+        assert_eq!(database.handlers["image/png"]["krita.desktop"].launch_count, 1);
+
+        // Pruning an entry drops its usage for every type
+        assert_eq!(database.prune(|key, _| key == "gimp.desktop"), ["gimp.desktop"]);
+        assert!(!database.handlers["image/png"].contains_key("gimp.desktop"));
+
+        // Usage for a type is pruned on its own, and empty types go with it
+        database.prune(|_, usage| usage.launch_count < 2);
+        assert!(database.handlers.is_empty());
+    }
+}
@@ -1,20 +1,29 @@
+use crate::config::CustomEntry;
 use anyhow::Result;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct AppEntry {
+    /// Stable key used for usage history: the desktop file ID for desktop
+    /// entries, or `path:<path>` for executables launched by path.
+    pub id: String,
     pub name: String,
     pub exec: String,
     pub icon: Option<String>,
     pub comment: Option<String>,
     pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    /// Types of files the application can open, from `MimeType=`
+    pub mime_types: Vec<String>,
     pub desktop_file: PathBuf,
     pub terminal: bool,
+    /// Set by `NoDisplay=true`: not listed, but can still open files
+    pub no_display: bool,
 }
 
 impl AppEntry {
-    pub fn from_path(path: &std::path::Path) -> Self {
+    pub fn from_path(path: &Path) -> Self {
         let name = path.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("Unknown")
@@ -24,23 +33,30 @@ impl AppEntry {
         let display_name = format!("{} [Path]", name);
 
         Self {
+            id: format!("path:{}", path.display()),
             name: display_name,
             exec: path.display().to_string(),
             icon: None,
             comment: Some(path.display().to_string()),
             categories: vec!["Path".to_string()],
+            keywords: Vec::new(),
+            mime_types: Vec::new(),
             desktop_file: PathBuf::new(),
             terminal: false,
+            no_display: false,
         }
     }
 
-    pub fn from_ini_file(path: PathBuf) -> Option<Self> {
+    /// Parses the desktop file at `path`, found below `applications_dir`.
+    pub fn from_ini_file(applications_dir: &Path, path: PathBuf) -> Option<Self> {
         let content = std::fs::read_to_string(&path).ok()?;
         let mut name = None;
         let mut exec = None;
         let mut icon = None;
         let mut comment = None;
         let mut categories = Vec::new();
+        let mut keywords = Vec::new();
+        let mut mime_types = Vec::new();
         let mut terminal = false;
         let mut no_display = false;
         let mut hidden = false;
@@ -79,6 +95,18 @@ impl AppEntry {
                         .map(|s| s.to_string())
                         .collect();
                 }
+                "Keywords" => {
+                    keywords = value.split(';')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string())
+                        .collect();
+                }
+                "MimeType" => {
+                    mime_types = value.split(';')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string())
+                        .collect();
+                }
                 "Terminal" => terminal = value.to_lowercase() == "true",
                 "NoDisplay" => no_display = value.to_lowercase() == "true",
                 "Hidden" => hidden = value.to_lowercase() == "true",
@@ -86,68 +114,357 @@ impl AppEntry {
             }
         }
 
-        if no_display || hidden {
+        // Hidden means deleted, while NoDisplay entries still handle files
+        if hidden {
             return None;
         }
 
         Some(Self {
+            id: desktop_file_id(applications_dir, &path)?,
             name: name?,
             exec: exec?,
             icon,
             comment,
             categories,
+            keywords,
+            mime_types,
             desktop_file: path,
             terminal,
+            no_display,
         })
     }
 
-    pub fn get_launch_command(&self) -> String {
-        let exec = self.exec.clone();
-        let exec = exec.replace("%F", "");
-        let exec = exec.replace("%U", "");
-        let exec = exec.replace("%f", "");
-        let exec = exec.replace("%u", "");
-        let exec = exec.replace("%i", "");
-        let exec = exec.replace("%c", &self.name);
-        let exec = exec.replace("%k", "");
-        exec.trim().to_string()
+    pub fn from_custom(entry: &CustomEntry) -> Self {
+        let exec = entry.argv
+            .iter()
+            .map(|arg| quote_exec_arg(&shellexpand::tilde(arg)))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            id: format!("custom:{}", entry.name),
+            name: entry.name.clone(),
+            exec,
+            icon: entry.icon.clone(),
+            comment: entry.comment.clone(),
+            categories: entry.categories.clone(),
+            keywords: entry.keywords.clone(),
+            mime_types: Vec::new(),
+            desktop_file: PathBuf::new(),
+            terminal: entry.terminal,
+            no_display: false,
+        }
+    }
+
+    /// Splits the Exec line into arguments, dropping field codes that need
+    /// files or URLs, expanding `%c` to the entry name and `%i` to
+    /// `--icon <Icon>`.
+    pub fn get_launch_args(&self) -> Vec<String> {
+        self.get_launch_args_for(None)
+    }
+
+    /// Like [`get_launch_args`](Self::get_launch_args), but substitutes
+    /// `file` for the `%f`, `%F`, `%u` or `%U` field code. Local files may
+    /// be passed as paths for URL codes, so the path is used for all of
+    /// them. Without a field code the file is appended, as GLib does.
+    pub fn get_launch_args_for(&self, file: Option<&Path>) -> Vec<String> {
+        let file = file.map(|file| file.to_string_lossy().to_string());
+        let mut substituted = false;
+        let mut args = Vec::new();
+        for arg in split_exec(&self.exec) {
+            // %i stands for two arguments, and only on its own
+            if arg == "%i" {
+                if let Some(icon) = &self.icon {
+                    args.push("--icon".to_string());
+                    args.push(icon.clone());
+                }
+                continue;
+            }
+
+            let mut expanded = String::new();
+            let mut has_code = false;
+            let mut chars = arg.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    expanded.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('%') => expanded.push('%'),
+                    // Only one file code is allowed, so later ones are
+                    // removed
+                    Some('f' | 'F' | 'u' | 'U') => {
+                        has_code = true;
+                        if let Some(file) = file.as_ref().filter(|_| !substituted) {
+                            substituted = true;
+                            expanded.push_str(file);
+                        }
+                    }
+                    Some('c') => {
+                        has_code = true;
+                        expanded.push_str(&self.name);
+                    }
+                    // Deprecated, unsupported or invalid codes are removed
+                    _ => has_code = true,
+                }
+            }
+            // An argument that was only field codes expanding to nothing
+            // is left out, rather than passed empty
+            if !(has_code && expanded.is_empty()) {
+                args.push(expanded);
+            }
+        }
+        if !substituted {
+            args.extend(file);
+        }
+        args
+    }
+
+    /// An entry with just an ID and a name, for tests to fill in the rest.
+    #[cfg(test)]
+    pub fn for_test(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            exec: String::new(),
+            icon: None,
+            comment: None,
+            categories: Vec::new(),
+            keywords: Vec::new(),
+            mime_types: Vec::new(),
+            desktop_file: PathBuf::new(),
+            terminal: false,
+            no_display: false,
+        }
+    }
+
+    /// Returns the first of the entry's categories that is a main category
+    /// of the desktop menu spec, used to group entries.
+    pub fn main_category(&self) -> Option<&'static str> {
+        self.categories
+            .iter()
+            .find_map(|category| MAIN_CATEGORIES.iter().find(|main| **main == category.as_str()).copied())
+    }
+}
+
+/// Main categories from the desktop menu specification; every entry should
+/// have one of these.
+const MAIN_CATEGORIES: &[&str] = &[
+    "AudioVideo",
+    "Audio",
+    "Video",
+    "Development",
+    "Education",
+    "Game",
+    "Graphics",
+    "Network",
+    "Office",
+    "Science",
+    "Settings",
+    "System",
+    "Utility",
+];
+
+/// Splits an Exec value into arguments following the quoting rules of the
+/// desktop entry spec: arguments containing reserved characters are wrapped
+/// in double quotes, inside which `"`, `` ` ``, `$` and `\` are escaped with
+/// a backslash.
+pub fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_arg = true;
+            }
+            '\\' if in_quotes => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+/// Quotes a single argument for use in an Exec value, the inverse of
+/// [`split_exec`]. `%` is doubled so the argument isn't taken for a field
+/// code.
+pub fn quote_exec_arg(arg: &str) -> String {
+    let arg = &arg.replace('%', "%%");
+    let reserved = |c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c);
+    if !arg.is_empty() && !arg.contains(reserved) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
     }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the desktop file ID of a desktop file below `applications_dir`:
+/// its path relative to that directory with `/` replaced by `-` (e.g.
+/// `org.mozilla.firefox.desktop`, or `kde4-kate.desktop` for
+/// `kde4/kate.desktop`), which stays the same across renames and locale
+/// changes.
+pub fn desktop_file_id(applications_dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(applications_dir).ok()?;
+    let components: Vec<&str> = relative.iter().map(|component| component.to_str()).collect::<Option<_>>()?;
+    (!components.is_empty()).then(|| components.join("-"))
 }
 
 pub struct DesktopScanner;
 
 impl DesktopScanner {
+    /// Returns the applications to list, leaving out `NoDisplay` entries.
     pub fn scan() -> Result<Vec<AppEntry>> {
+        let mut apps = Self::scan_all()?;
+        apps.retain(|app| !app.no_display);
+        Ok(apps)
+    }
+
+    /// Returns every application including `NoDisplay` entries, which are
+    /// candidates for opening files only.
+    pub fn scan_all() -> Result<Vec<AppEntry>> {
         let mut apps = HashMap::new();
 
+        // Least important first: system entries, then flatpak exports, then
+        // the user's own entries, which override all others
         let desktop_dirs = vec![
             "/usr/share/applications",
             "/usr/local/share/applications",
-            "~/.local/share/applications",
             "/var/lib/flatpak/exports/share/applications",
             "~/.local/share/flatpak/exports/share/applications",
+            "~/.local/share/applications",
         ];
 
         for dir in desktop_dirs {
             let expanded = shellexpand::tilde(dir);
-            let path = std::path::Path::new(expanded.as_ref());
+            let path = Path::new(expanded.as_ref());
 
             if !path.exists() {
                 continue;
             }
 
-            if let Ok(entries) = std::fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("desktop") {
-                        if let Some(app) = AppEntry::from_ini_file(path) {
-                            apps.insert(app.name.clone(), app);
-                        }
-                    }
+            Self::scan_dir(path, path, &mut apps);
+        }
+
+        Ok(apps.into_values().collect())
+    }
+
+    /// Adds the desktop files in `dir` and its subdirectories, which are
+    /// below `applications_dir`, to `apps`.
+    fn scan_dir(applications_dir: &Path, dir: &Path, apps: &mut HashMap<String, AppEntry>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Symlinked directories aren't followed, so loops can't recurse
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                Self::scan_dir(applications_dir, &path, apps);
+            } else if path.extension().and_then(|s| s.to_str()) == Some("desktop") {
+                if let Some(app) = AppEntry::from_ini_file(applications_dir, path) {
+                    // Later directories take precedence
+                    apps.insert(app.id.clone(), app);
                 }
             }
         }
+    }
+}
 
-        Ok(apps.into_values().collect())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_arguments_round_trip() {
+        let argv = ["printf", "%u %f", "100%", "%%", "it's \"quoted\" $HOME", ""];
+        let entry = CustomEntry {
+            name: "test".to_string(),
+            argv: argv.iter().map(|arg| arg.to_string()).collect(),
+            terminal: false,
+            icon: None,
+            comment: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+        };
+        let app = AppEntry::from_custom(&entry);
+        assert_eq!(split_exec(&app.exec).len(), argv.len());
+        assert_eq!(app.get_launch_args(), argv);
+        assert_eq!(app.get_launch_args_for(Some(Path::new("/tmp/a"))), [&argv[..], &["/tmp/a"]].concat());
+    }
+
+    #[test]
+    fn expands_field_codes_inside_arguments() {
+        let app = AppEntry {
+            exec: "viewer --file=%f %u%U --title=%c %i 100%%".to_string(),
+            icon: Some("viewer-icon".to_string()),
+            ..AppEntry::for_test("viewer.desktop", "Viewer")
+        };
+        assert_eq!(
+            app.get_launch_args_for(Some(Path::new("/tmp/a"))),
+            ["viewer", "--file=/tmp/a", "--title=Viewer", "--icon", "viewer-icon", "100%"]
+        );
+        assert_eq!(app.get_launch_args(), ["viewer", "--file=", "--title=Viewer", "--icon", "viewer-icon", "100%"]);
+
+        let no_icon = AppEntry { icon: None, ..app };
+        assert_eq!(no_icon.get_launch_args(), ["viewer", "--file=", "--title=Viewer", "100%"]);
+    }
+
+    #[test]
+    fn desktop_file_ids_include_subdirectories() {
+        let dir = Path::new("/usr/share/applications");
+        assert_eq!(desktop_file_id(dir, &dir.join("firefox.desktop")).as_deref(), Some("firefox.desktop"));
+        assert_eq!(desktop_file_id(dir, &dir.join("kde4/kate.desktop")).as_deref(), Some("kde4-kate.desktop"));
+        assert_eq!(desktop_file_id(dir, Path::new("/opt/kate.desktop")), None);
+    }
+
+    #[test]
+    fn keeps_no_display_entries_as_handlers() {
+        let dir = std::env::temp_dir().join(format!("exek-desktop-entry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let parse = |name: &str, extra: &str| {
+            let path = dir.join(name);
+            let content = format!("[Desktop Entry]\nName=Viewer\nExec=viewer %f\nMimeType=image/png;\n{}", extra);
+            std::fs::write(&path, content).unwrap();
+            AppEntry::from_ini_file(&dir, path)
+        };
+        let shown = parse("shown.desktop", "");
+        let no_display = parse("userapp-viewer.desktop", "NoDisplay=true\n");
+        let hidden = parse("hidden.desktop", "Hidden=true\n");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(!shown.unwrap().no_display);
+        let no_display = no_display.unwrap();
+        assert!(no_display.no_display);
+        assert_eq!(no_display.mime_types, ["image/png"]);
+        assert!(hidden.is_none());
     }
 }
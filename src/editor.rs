@@ -0,0 +1,245 @@
+use crate::input::InputLine;
+use std::collections::VecDeque;
+
+/// Number of killed texts kept for yanking
+const KILL_RING_SIZE: usize = 16;
+
+/// An editing command for the search box, following readline's Emacs mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Insert(char),
+    DeleteBefore,
+    DeleteAfter,
+    Left,
+    Right,
+    Home,
+    End,
+    /// To the start of the previous alphanumeric word
+    WordLeft,
+    /// To the end of the next alphanumeric word
+    WordRight,
+    /// Kill the alphanumeric word before the cursor
+    KillWordBefore,
+    /// Kill back to the previous whitespace
+    KillToWhitespace,
+    KillToStart,
+    KillToEnd,
+    /// Kill the whole line, wherever the cursor is
+    KillLine,
+    /// Insert the last killed text
+    Yank,
+    /// Right after a yank, replace it with the text killed before
+    YankPop,
+}
+
+impl Edit {
+    fn is_kill(&self) -> bool {
+        matches!(
+            self,
+            Edit::KillWordBefore
+                | Edit::KillToWhitespace
+                | Edit::KillToStart
+                | Edit::KillToEnd
+                | Edit::KillLine
+        )
+    }
+}
+
+/// Applies [`Edit`]s to an [`InputLine`], keeping the kill ring between
+/// them. Consecutive kills are joined into one ring entry, as in readline.
+#[derive(Debug, Default)]
+pub struct Editor {
+    /// Most recent kill first
+    kill_ring: VecDeque<String>,
+    last: Option<Edit>,
+    /// Start of the text inserted by the last yank and its ring index
+    yanked: Option<(usize, usize)>,
+}
+
+impl Editor {
+    /// Applies `edit` to `line`, returning whether the text changed.
+    pub fn apply(&mut self, line: &mut InputLine, edit: Edit) -> bool {
+        let changed = match edit {
+            Edit::Insert(c) => {
+                line.insert_char(c);
+                true
+            }
+            Edit::DeleteBefore => line.delete_before(),
+            Edit::DeleteAfter => line.delete_after(),
+            Edit::Left => {
+                line.move_left();
+                false
+            }
+            Edit::Right => {
+                line.move_right();
+                false
+            }
+            Edit::Home => {
+                line.move_home();
+                false
+            }
+            Edit::End => {
+                line.move_end();
+                false
+            }
+            Edit::WordLeft => {
+                line.set_cursor(line.word_start_before(char::is_alphanumeric));
+                false
+            }
+            Edit::WordRight => {
+                line.set_cursor(line.word_end_after(char::is_alphanumeric));
+                false
+            }
+            Edit::KillWordBefore => self.kill(line, line.word_start_before(char::is_alphanumeric)),
+            Edit::KillToWhitespace => self.kill(line, line.word_start_before(|c| !c.is_whitespace())),
+            Edit::KillToStart => self.kill(line, 0),
+            Edit::KillToEnd => self.kill(line, line.text().len()),
+            Edit::KillLine => {
+                line.move_home();
+                self.kill(line, line.text().len())
+            }
+            Edit::Yank => self.yank(line, 0),
+            Edit::YankPop => match self.yanked.filter(|_| matches!(self.last, Some(Edit::Yank | Edit::YankPop))) {
+                Some((start, index)) => {
+                    line.remove_to(start);
+                    self.yank(line, (index + 1) % self.kill_ring.len())
+                }
+                None => false,
+            },
+        };
+
+        if !matches!(edit, Edit::Yank | Edit::YankPop) {
+            self.yanked = None;
+        }
+        self.last = Some(edit);
+        changed
+    }
+
+    /// Removes the text between the cursor and `other` and pushes it onto
+    /// the kill ring.
+    fn kill(&mut self, line: &mut InputLine, other: usize) -> bool {
+        let backward = other < line.cursor();
+        let killed = line.remove_to(other);
+        if killed.is_empty() {
+            return false;
+        }
+
+        match self.kill_ring.front_mut() {
+            Some(previous) if self.last.is_some_and(|last| last.is_kill()) => {
+                if backward {
+                    previous.insert_str(0, &killed);
+                } else {
+                    previous.push_str(&killed);
+                }
+            }
+            _ => {
+                self.kill_ring.push_front(killed);
+                self.kill_ring.truncate(KILL_RING_SIZE);
+            }
+        }
+        true
+    }
+
+    fn yank(&mut self, line: &mut InputLine, index: usize) -> bool {
+        let Some(text) = self.kill_ring.get(index) else {
+            return false;
+        };
+        let start = line.cursor();
+        line.insert_str(text);
+        self.yanked = Some((start, index));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> InputLine {
+        let mut line = InputLine::default();
+        line.set_text(text);
+        line
+    }
+
+    fn apply(editor: &mut Editor, line: &mut InputLine, edits: &[Edit]) {
+        for edit in edits {
+            editor.apply(line, *edit);
+        }
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let mut editor = Editor::default();
+        let mut line = line("foo-bar  baz");
+
+        apply(&mut editor, &mut line, &[Edit::WordLeft]);
+        assert_eq!(line.cursor(), 9);
+        apply(&mut editor, &mut line, &[Edit::WordLeft]);
+        assert_eq!(line.cursor(), 4);
+        apply(&mut editor, &mut line, &[Edit::WordLeft, Edit::WordLeft]);
+        assert_eq!(line.cursor(), 0);
+
+        apply(&mut editor, &mut line, &[Edit::WordRight]);
+        assert_eq!(line.cursor(), 3);
+        apply(&mut editor, &mut line, &[Edit::WordRight]);
+        assert_eq!(line.cursor(), 7);
+    }
+
+    #[test]
+    fn kills_words() {
+        let mut editor = Editor::default();
+
+        let mut text = line("~/src/exek-launcher");
+        apply(&mut editor, &mut text, &[Edit::KillWordBefore]);
+        assert_eq!(text.text(), "~/src/exek-");
+
+        let mut text = line("open ~/src/exek");
+        apply(&mut editor, &mut text, &[Edit::KillToWhitespace]);
+        assert_eq!(text.text(), "open ");
+    }
+
+    #[test]
+    fn kills_to_start_and_end() {
+        let mut editor = Editor::default();
+        let mut text = line("firefox private");
+        apply(&mut editor, &mut text, &[Edit::WordLeft, Edit::Left, Edit::KillToEnd]);
+        assert_eq!(text.text(), "firefox");
+        apply(&mut editor, &mut text, &[Edit::Left, Edit::KillToStart]);
+        assert_eq!(text.text(), "x");
+        assert_eq!(text.cursor(), 0);
+
+        let mut text = line("firefox private");
+        apply(&mut editor, &mut text, &[Edit::WordLeft, Edit::KillLine]);
+        assert_eq!(text.text(), "");
+    }
+
+    #[test]
+    fn yanks_and_joins_consecutive_kills() {
+        let mut editor = Editor::default();
+        let mut text = line("one two three");
+        apply(&mut editor, &mut text, &[Edit::KillWordBefore, Edit::KillWordBefore]);
+        assert_eq!(text.text(), "one ");
+
+        apply(&mut editor, &mut text, &[Edit::Home, Edit::Yank]);
+        assert_eq!(text.text(), "two threeone ");
+    }
+
+    #[test]
+    fn yank_pop_cycles_through_the_ring() {
+        let mut editor = Editor::default();
+        let mut text = line("alpha beta");
+        apply(&mut editor, &mut text, &[Edit::KillWordBefore, Edit::Left, Edit::KillWordBefore]);
+        assert_eq!(text.text(), " ");
+
+        apply(&mut editor, &mut text, &[Edit::End, Edit::Yank]);
+        assert_eq!(text.text(), " alpha");
+        apply(&mut editor, &mut text, &[Edit::YankPop]);
+        assert_eq!(text.text(), " beta");
+        apply(&mut editor, &mut text, &[Edit::YankPop]);
+        assert_eq!(text.text(), " alpha");
+
+        // Only directly after a yank
+        apply(&mut editor, &mut text, &[Edit::Left, Edit::YankPop]);
+        assert_eq!(text.text(), " alpha");
+    }
+}
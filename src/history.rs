@@ -0,0 +1,338 @@
+use crate::cli::{HistoryCommand, OutputArgs};
+use crate::config::Config;
+use crate::database::{AppUsage, Database};
+use crate::desktop_entry::{AppEntry, DesktopScanner};
+use crate::importers;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct HistoryRow {
+    id: String,
+    name: Option<String>,
+    launch_count: u32,
+    last_launched: Option<DateTime<Utc>>,
+    frecency: f64,
+}
+
+pub fn run(command: HistoryCommand) -> Result<()> {
+    let apps = load_apps()?;
+    let mut database = Database::load()?;
+    if database.migrate(&apps) {
+        database.save()?;
+    }
+
+    match command {
+        HistoryCommand::List(output) => {
+            let mut rows = collect_rows(&database, &apps);
+            rows.sort_by(|a, b| {
+                b.last_launched
+                    .cmp(&a.last_launched)
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+            print_rows(&rows, &output)?;
+        }
+        HistoryCommand::Top { limit, output } => {
+            let mut rows = collect_rows(&database, &apps);
+            rows.sort_by(|a, b| {
+                b.frecency
+                    .partial_cmp(&a.frecency)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+            rows.truncate(limit);
+            print_rows(&rows, &output)?;
+        }
+        HistoryCommand::Remove { id } => {
+            if database.remove(&id).is_none() {
+                anyhow::bail!("No history entry with ID '{}'", id);
+            }
+            database.save()?;
+            println!("Removed {}", id);
+        }
+        HistoryCommand::Reset => {
+            let count = database.usage.len();
+            database.reset();
+            database.save()?;
+            println!("Removed {} entries", count);
+        }
+        HistoryCommand::Prune { older_than, missing } => {
+            // An age reaching past the earliest representable time leaves
+            // nothing older
+            let cutoff = older_than.and_then(|age| Utc::now().checked_sub_signed(age));
+            let removed = database.prune(|key, usage| should_prune(key, usage, cutoff, missing));
+            database.save()?;
+
+            for key in &removed {
+                println!("Removed {}", key);
+            }
+            println!("Pruned {} entries", removed.len());
+        }
+        HistoryCommand::Export { file } => {
+            let data = serde_json::to_string_pretty(&database)?;
+            match file {
+                Some(path) => fs::write(&path, data)
+                    .with_context(|| format!("Could not write {}", path.display()))?,
+                None => println!("{}", data),
+            }
+        }
+        HistoryCommand::Import { file, replace } => {
+            let data = if file.as_os_str() == "-" {
+                let mut data = String::new();
+                io::stdin().read_to_string(&mut data)?;
+                data
+            } else {
+                fs::read_to_string(&file)
+                    .with_context(|| format!("Could not read {}", file.display()))?
+            };
+
+            let mut imported: Database = serde_json::from_str(&data)?;
+            imported.migrate(&apps);
+
+            if replace {
+                database.reset();
+            }
+            database.merge(&imported);
+            database.save()?;
+            println!("Imported {} entries", imported.usage.len());
+        }
+        HistoryCommand::ImportFrom { launcher, file, dry_run } => {
+            let report = importers::read_history(launcher, file.as_deref(), &apps)?;
+            if report.files.is_empty() {
+                anyhow::bail!("No history files found for {:?}", launcher);
+            }
+
+            for path in &report.files {
+                println!("Reading {}", path.display());
+            }
+            for entry in report.matched() {
+                println!(
+                    "  {:>5}  {} -> {}",
+                    entry.count,
+                    entry.source,
+                    entry.key.as_deref().unwrap_or_default()
+                );
+            }
+            for entry in report.unmatched() {
+                println!("  {:>5}  {} (no matching application)", entry.count, entry.source);
+            }
+
+            let matched = report.matched().count();
+            let unmatched = report.unmatched().count();
+            if dry_run {
+                println!("Would import {} entries, {} unmatched (dry run)", matched, unmatched);
+            } else {
+                importers::apply(&report, &mut database);
+                database.save()?;
+                println!("Imported {} entries, {} unmatched", matched, unmatched);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pins or unpins `id`, or lists the pinned entries when no ID is given.
+pub fn set_pinned(id: Option<String>, pinned: bool) -> Result<()> {
+    let mut database = Database::load()?;
+    let Some(id) = id else {
+        for id in &database.pinned {
+            println!("{}", id);
+        }
+        return Ok(());
+    };
+
+    if pinned {
+        check_known(&id, &database)?;
+    } else if !database.is_pinned(&id) {
+        anyhow::bail!("'{}' is not pinned", id);
+    }
+
+    if database.set_pinned(&id, pinned) {
+        database.save()?;
+    }
+    println!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, id);
+    Ok(())
+}
+
+/// Hides or unhides `id`, or lists the hidden entries when no ID is given.
+pub fn set_hidden(id: Option<String>, hidden: bool) -> Result<()> {
+    let mut database = Database::load()?;
+    let Some(id) = id else {
+        for id in &database.hidden {
+            println!("{}", id);
+        }
+        return Ok(());
+    };
+
+    if hidden {
+        check_known(&id, &database)?;
+    } else if !database.is_hidden(&id) {
+        anyhow::bail!("'{}' is not hidden", id);
+    }
+
+    if database.set_hidden(&id, hidden) {
+        database.save()?;
+    }
+    println!("{} {}", if hidden { "Hidden" } else { "Unhidden" }, id);
+    Ok(())
+}
+
+/// Desktop applications and custom entries, the entries IDs can refer to
+/// besides `path:` keys.
+fn load_apps() -> Result<Vec<AppEntry>> {
+    let mut apps = DesktopScanner::scan()?;
+    apps.extend(Config::load()?.custom_apps());
+    Ok(apps)
+}
+
+/// Fails unless `id` names an application, a custom entry, or a path that
+/// exists or has been launched, so typos don't create dead pins.
+fn check_known(id: &str, database: &Database) -> Result<()> {
+    let known = match id.strip_prefix("path:") {
+        Some(path) => Path::new(path).exists() || database.usage.contains_key(id),
+        None => load_apps()?.iter().any(|app| app.id == id),
+    };
+    if !known {
+        anyhow::bail!("No application or path with ID '{}'", id);
+    }
+    Ok(())
+}
+
+/// Whether `prune` removes an entry: one last launched before `cutoff` (or
+/// never), or with `missing` a `path:` entry whose file is gone.
+fn should_prune(key: &str, usage: &AppUsage, cutoff: Option<DateTime<Utc>>, missing: bool) -> bool {
+    let too_old = cutoff.is_some_and(|cutoff| {
+        usage.last_launched.is_none_or(|last| last < cutoff)
+    });
+    let is_missing = missing
+        && key
+            .strip_prefix("path:")
+            .is_some_and(|path| !Path::new(path).exists());
+    too_old || is_missing
+}
+
+fn collect_rows(database: &Database, apps: &[AppEntry]) -> Vec<HistoryRow> {
+    let names: HashMap<&str, &str> = apps
+        .iter()
+        .map(|app| (app.id.as_str(), app.name.as_str()))
+        .collect();
+
+    database
+        .usage
+        .iter()
+        .map(|(key, usage)| HistoryRow {
+            id: key.clone(),
+            name: names
+                .get(key.as_str())
+                .map(|name| name.to_string())
+                .or_else(|| existing_file_name(key)),
+            launch_count: usage.launch_count,
+            last_launched: usage.last_launched,
+            frecency: database.calculate_frecency(key),
+        })
+        .collect()
+}
+
+/// Returns the file name for `path:` keys that still point at an existing file.
+fn existing_file_name(key: &str) -> Option<String> {
+    let path = Path::new(key.strip_prefix("path:")?);
+    if !path.exists() {
+        return None;
+    }
+    path.file_name().map(|n| n.to_string_lossy().to_string())
+}
+
+fn print_rows(rows: &[HistoryRow], output: &OutputArgs) -> Result<()> {
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(rows)?);
+        return Ok(());
+    }
+
+    let table: Vec<[String; 5]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.id.clone(),
+                row.name.clone().unwrap_or_else(|| "-".to_string()),
+                row.launch_count.to_string(),
+                row.last_launched
+                    .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string()),
+                format!("{:.1}", row.frecency),
+            ]
+        })
+        .collect();
+
+    let header = ["ID", "NAME", "COUNT", "LAST LAUNCHED", "FRECENCY"];
+    let mut widths = header.map(str::len);
+    for row in &table {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: [&str; 5]| {
+        format!(
+            "{:<w0$}  {:<w1$}  {:>w2$}  {:<w3$}  {:>w4$}",
+            cells[0],
+            cells[1],
+            cells[2],
+            cells[3],
+            cells[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        )
+    };
+
+    println!("{}", format_row(header).trim_end());
+    for row in &table {
+        println!("{}", format_row(row.each_ref().map(String::as_str)).trim_end());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn usage(days_ago: Option<i64>) -> AppUsage {
+        AppUsage {
+            launch_count: 1,
+            last_launched: days_ago.map(|days| Utc::now() - Duration::days(days)),
+        }
+    }
+
+    #[test]
+    fn prunes_old_and_missing_entries() {
+        let mut database = Database::new();
+        database.usage.insert("recent.desktop".to_string(), usage(Some(3)));
+        database.usage.insert("old.desktop".to_string(), usage(Some(200)));
+        database.usage.insert("never.desktop".to_string(), usage(None));
+        database.usage.insert("path:/".to_string(), usage(Some(200)));
+        database.usage.insert("path:/no/such/exek/file".to_string(), usage(Some(3)));
+
+        let mut by_age = database.clone();
+        let cutoff = Some(Utc::now() - Duration::days(90));
+        let removed = by_age.prune(|key, usage| should_prune(key, usage, cutoff, false));
+        assert_eq!(removed, ["never.desktop", "old.desktop", "path:/"]);
+
+        let mut by_file = database.clone();
+        let removed = by_file.prune(|key, usage| should_prune(key, usage, None, true));
+        assert_eq!(removed, ["path:/no/such/exek/file"]);
+        assert_eq!(by_file.usage.len(), 4);
+
+        let removed = database.prune(|key, usage| should_prune(key, usage, None, false));
+        assert!(removed.is_empty());
+    }
+}
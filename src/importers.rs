@@ -0,0 +1,250 @@
+use crate::cli::Launcher;
+use crate::database::{AppUsage, Database};
+use crate::desktop_entry::{desktop_file_id, AppEntry};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single history line read from another launcher's cache.
+pub struct ImportedEntry {
+    pub source: String,
+    pub count: u32,
+    /// Database key the entry maps to, if it could be resolved
+    pub key: Option<String>,
+}
+
+pub struct ImportReport {
+    pub files: Vec<PathBuf>,
+    pub entries: Vec<ImportedEntry>,
+}
+
+impl ImportReport {
+    pub fn matched(&self) -> impl Iterator<Item = &ImportedEntry> {
+        self.entries.iter().filter(|e| e.key.is_some())
+    }
+
+    pub fn unmatched(&self) -> impl Iterator<Item = &ImportedEntry> {
+        self.entries.iter().filter(|e| e.key.is_none())
+    }
+}
+
+/// Default cache files for each launcher, relative to `$XDG_CACHE_HOME`.
+fn cache_files(launcher: Launcher) -> &'static [&'static str] {
+    match launcher {
+        Launcher::Rofi => &["rofi3.druncache", "rofi3.runcache", "rofi-history"],
+        Launcher::Wofi => &["wofi-drun", "wofi-run"],
+        Launcher::Fuzzel => &["fuzzel"],
+        Launcher::Dmenu => &["dmenu_run"],
+    }
+}
+
+/// Reads the history of `launcher` (or `file` if given) and resolves each
+/// entry to a desktop file ID or `path:` key. Nothing is written.
+pub fn read_history(launcher: Launcher, file: Option<&Path>, apps: &[AppEntry]) -> Result<ImportReport> {
+    let files: Vec<PathBuf> = match file {
+        Some(file) => vec![file.to_path_buf()],
+        None => {
+            let cache_dir = dirs::cache_dir()
+                .ok_or_else(|| anyhow::anyhow!("Could not find cache directory"))?;
+            cache_files(launcher)
+                .iter()
+                .map(|name| cache_dir.join(name))
+                .filter(|path| path.exists())
+                .collect()
+        }
+    };
+
+    let mut entries = Vec::new();
+    for path in &files {
+        let content = fs::read_to_string(path)?;
+        for line in content.lines() {
+            if let Some((source, count)) = parse_line(line) {
+                entries.push(ImportedEntry {
+                    key: resolve_key(&source, apps),
+                    source,
+                    count,
+                });
+            }
+        }
+    }
+
+    Ok(ImportReport { files, entries })
+}
+
+/// Merges the matched entries of `report` into `database`. The caches don't
+/// record launch times, so the modification time of the cache file is used.
+pub fn apply(report: &ImportReport, database: &mut Database) {
+    let last_launched = report
+        .files
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(Utc::now);
+
+    for entry in report.matched() {
+        if let Some(key) = &entry.key {
+            database.merge_usage(
+                key,
+                &AppUsage {
+                    launch_count: entry.count,
+                    last_launched: Some(last_launched),
+                },
+            );
+        }
+    }
+}
+
+/// Returns the desktop file ID of a desktop file ID or path: the path below
+/// the `applications` directory with `/` replaced by `-`, so
+/// `/usr/share/applications/kde4/kate.desktop` is `kde4-kate.desktop`.
+fn desktop_id_of(source: &str) -> String {
+    let path = Path::new(source);
+    path.ancestors()
+        .skip(1)
+        .filter(|dir| dir.file_name().is_some_and(|name| name == "applications"))
+        .find_map(|dir| desktop_file_id(dir, path))
+        .or_else(|| path.file_name().and_then(|name| name.to_str()).map(str::to_string))
+        .unwrap_or_else(|| source.to_string())
+}
+
+/// Parses a history line into an entry and its launch count. rofi and wofi
+/// write `<count> <entry>`, fuzzel writes `<entry>|<count>`, and history
+/// patched dmenu_run scripts write `<count>\t<entry>`. Lines without a count,
+/// such as the plain executable list of stock dmenu, count as one launch;
+/// repeated lines add up when imported.
+fn parse_line(line: &str) -> Option<(String, u32)> {
+    let line = line.trim();
+    // A count without an entry
+    if line.is_empty() || line.parse::<u32>().is_ok() {
+        return None;
+    }
+
+    if let Some((entry, count)) = line.rsplit_once('|') {
+        if let Ok(count) = count.trim().parse() {
+            return Some((entry.trim().to_string(), count));
+        }
+    }
+
+    let Some((count, entry)) = line
+        .split_once(char::is_whitespace)
+        .and_then(|(count, entry)| Some((count.parse().ok()?, entry)))
+    else {
+        return Some((line.to_string(), 1));
+    };
+    let entry = entry.trim();
+    if entry.is_empty() {
+        return None;
+    }
+    Some((entry.to_string(), count))
+}
+
+/// Maps a launcher's entry to a database key: desktop file IDs or paths to
+/// desktop files become desktop file IDs, application names are matched
+/// against the scanned entries, and commands are resolved to `path:` keys.
+/// Desktop files that aren't scanned entries (such as `NoDisplay` ones)
+/// don't resolve.
+fn resolve_key(source: &str, apps: &[AppEntry]) -> Option<String> {
+    let is_desktop_file = source.ends_with(".desktop");
+    let desktop_id = if is_desktop_file {
+        desktop_id_of(source)
+    } else {
+        let file_name = Path::new(source)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(source);
+        format!("{}.desktop", file_name)
+    };
+
+    if let Some(app) = apps.iter().find(|app| app.id == desktop_id) {
+        return Some(app.id.clone());
+    }
+
+    if let Some(app) = apps.iter().find(|app| app.name == source) {
+        return Some(app.id.clone());
+    }
+
+    if is_desktop_file {
+        return None;
+    }
+
+    let command = source.split_whitespace().next()?;
+    let path = if command.contains('/') {
+        let expanded = PathBuf::from(shellexpand::tilde(command).as_ref());
+        expanded.is_file().then_some(expanded)?
+    } else {
+        which::which(command).ok()?
+    };
+
+    Some(format!("path:{}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, count: u32) -> Option<(String, u32)> {
+        Some((source.to_string(), count))
+    }
+
+    #[test]
+    fn parses_rofi_and_wofi_lines() {
+        assert_eq!(parse_line("12 firefox.desktop"), entry("firefox.desktop", 12));
+        assert_eq!(parse_line("3 /usr/share/applications/org.gnome.gedit.desktop"), entry("/usr/share/applications/org.gnome.gedit.desktop", 3));
+        assert_eq!(parse_line("2 htop -t"), entry("htop -t", 2));
+        assert_eq!(parse_line("5 "), None);
+    }
+
+    #[test]
+    fn parses_fuzzel_lines() {
+        assert_eq!(parse_line("firefox.desktop|7"), entry("firefox.desktop", 7));
+        assert_eq!(parse_line("Visual Studio Code|1"), entry("Visual Studio Code", 1));
+    }
+
+    #[test]
+    fn parses_dmenu_lines() {
+        assert_eq!(parse_line("4\tfirefox"), entry("firefox", 4));
+        // Stock dmenu_run caches list executables without counts
+        assert_eq!(parse_line("firefox"), entry("firefox", 1));
+        assert_eq!(parse_line("  "), None);
+    }
+
+    #[test]
+    fn imports_plain_dmenu_cache() {
+        let dir = std::env::temp_dir().join(format!("exek-importers-dmenu-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cache = dir.join("dmenu_run");
+        fs::write(&cache, "firefox\nkate\nfirefox\n").unwrap();
+        let apps = [AppEntry::for_test("firefox.desktop", "Firefox"), AppEntry::for_test("kate.desktop", "Kate")];
+        let report = read_history(Launcher::Dmenu, Some(&cache), &apps).unwrap();
+        let mut database = Database::new();
+        apply(&report, &mut database);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(report.matched().count(), 3);
+        assert_eq!(database.get_usage("firefox.desktop").launch_count, 2);
+        assert_eq!(database.get_usage("kate.desktop").launch_count, 1);
+    }
+
+    #[test]
+    fn resolves_only_scanned_desktop_files() {
+        let apps = [AppEntry::for_test("firefox.desktop", "Firefox"), AppEntry::for_test("kde4-kate.desktop", "Kate")];
+        assert_eq!(resolve_key("firefox.desktop", &apps).as_deref(), Some("firefox.desktop"));
+        assert_eq!(resolve_key("firefox", &apps).as_deref(), Some("firefox.desktop"));
+        assert_eq!(resolve_key("Kate", &apps).as_deref(), Some("kde4-kate.desktop"));
+        assert_eq!(
+            resolve_key("/usr/share/applications/kde4/kate.desktop", &apps).as_deref(),
+            Some("kde4-kate.desktop")
+        );
+
+        // An existing desktop file that isn't a scanned entry, e.g. NoDisplay
+        let dir = std::env::temp_dir().join(format!("exek-importers-{}", std::process::id()));
+        fs::create_dir_all(dir.join("applications")).unwrap();
+        let hidden = dir.join("applications/hidden.desktop");
+        fs::write(&hidden, "[Desktop Entry]\nNoDisplay=true\n").unwrap();
+        let key = resolve_key(&hidden.display().to_string(), &apps);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(key, None);
+    }
+}
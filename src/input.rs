@@ -0,0 +1,278 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A single-line text input edited by grapheme cluster, so a character made
+/// of several code points (`e` plus a combining accent, flags, emoji
+/// sequences) is moved over and deleted as one. The cursor is a byte offset
+/// into the text that always sits on a grapheme boundary.
+#[derive(Debug, Clone, Default)]
+pub struct InputLine {
+    text: String,
+    cursor: usize,
+    /// Display column shown at the left edge, for text wider than the box
+    scroll: usize,
+}
+
+/// Display width as the terminal renders it, one grapheme at a time.
+fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(|grapheme| grapheme.width()).sum()
+}
+
+impl InputLine {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte offset of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the text and moves the cursor to its end.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.len();
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.insert_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+        // Inserting a base character before a combining mark merges them
+        // into one grapheme, so step over the mark
+        if !self.is_boundary(self.cursor) {
+            self.cursor = self.next_boundary();
+        }
+    }
+
+    /// Deletes the grapheme before the cursor, returning whether the text
+    /// changed.
+    pub fn delete_before(&mut self) -> bool {
+        let start = self.prev_boundary();
+        if start == self.cursor {
+            return false;
+        }
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        true
+    }
+
+    /// Deletes the grapheme after the cursor, returning whether the text
+    /// changed.
+    pub fn delete_after(&mut self) -> bool {
+        let end = self.next_boundary();
+        if end == self.cursor {
+            return false;
+        }
+        self.text.replace_range(self.cursor..end, "");
+        true
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.prev_boundary();
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Moves the cursor to `cursor`, or the next grapheme boundary after it.
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.text.len());
+        if !self.is_boundary(self.cursor) {
+            self.cursor = self.next_boundary();
+        }
+    }
+
+    /// Removes the text between the cursor and `other`, leaving the cursor
+    /// at the start of the removed range, and returns it.
+    pub fn remove_to(&mut self, other: usize) -> String {
+        let range = self.cursor.min(other)..self.cursor.max(other);
+        self.cursor = range.start;
+        self.text.drain(range).collect()
+    }
+
+    /// Returns the start of the word before the cursor, skipping separators
+    /// between the cursor and the word. `is_word` tells word characters from
+    /// separators.
+    pub fn word_start_before(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let is_word = |grapheme: &str| grapheme.chars().next().is_some_and(&is_word);
+        let mut graphemes = self.text[..self.cursor].grapheme_indices(true).rev().peekable();
+        let mut start = self.cursor;
+        while let Some((index, _)) = graphemes.next_if(|(_, grapheme)| !is_word(grapheme)) {
+            start = index;
+        }
+        while let Some((index, _)) = graphemes.next_if(|(_, grapheme)| is_word(grapheme)) {
+            start = index;
+        }
+        start
+    }
+
+    /// Returns the end of the word after the cursor, skipping separators
+    /// between the cursor and the word.
+    pub fn word_end_after(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let is_word = |grapheme: &str| grapheme.chars().next().is_some_and(&is_word);
+        let mut graphemes = self.text[self.cursor..].grapheme_indices(true).peekable();
+        let mut end = self.cursor;
+        while let Some((index, grapheme)) = graphemes.next_if(|(_, grapheme)| !is_word(grapheme)) {
+            end = self.cursor + index + grapheme.len();
+        }
+        while let Some((index, grapheme)) = graphemes.next_if(|(_, grapheme)| is_word(grapheme)) {
+            end = self.cursor + index + grapheme.len();
+        }
+        end
+    }
+
+    /// Display column of the cursor, counting wide characters as two.
+    pub fn cursor_column(&self) -> usize {
+        display_width(&self.text[..self.cursor])
+    }
+
+    /// Returns the part of the text that fits in `width` columns and the
+    /// cursor's column within it, scrolling horizontally to keep the cursor
+    /// in view. A wide character cut by the left edge is left out.
+    pub fn view(&mut self, width: usize) -> (&str, usize) {
+        if width == 0 {
+            return ("", 0);
+        }
+
+        // Scroll back when the text got shorter, keeping a column for the
+        // cursor after the last character
+        let cursor_column = self.cursor_column();
+        self.scroll = self.scroll.min((display_width(&self.text) + 1).saturating_sub(width));
+        if cursor_column < self.scroll {
+            self.scroll = cursor_column;
+        } else if cursor_column >= self.scroll + width {
+            self.scroll = cursor_column + 1 - width;
+        }
+
+        let mut start = None;
+        let mut end = self.text.len();
+        let mut column = 0;
+        for (index, grapheme) in self.text.grapheme_indices(true) {
+            if start.is_none() && column >= self.scroll {
+                start = Some((index, column));
+            }
+            let grapheme_width = grapheme.width();
+            if column + grapheme_width > self.scroll + width {
+                end = index;
+                break;
+            }
+            column += grapheme_width;
+        }
+
+        let (start, start_column) = start.unwrap_or((end, self.scroll));
+        (&self.text[start..end], cursor_column.saturating_sub(start_column))
+    }
+
+    fn is_boundary(&self, index: usize) -> bool {
+        index == self.text.len() || self.text.grapheme_indices(true).any(|(start, _)| start == index)
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .map(|(index, grapheme)| index + grapheme.len())
+            .find(|&end| end > self.cursor)
+            .unwrap_or(self.text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> InputLine {
+        let mut input = InputLine::default();
+        text.chars().for_each(|c| input.insert_char(c));
+        input
+    }
+
+    #[test]
+    fn edits_non_ascii_text() {
+        let mut input = typed("héllo wörld");
+        input.move_left();
+        input.move_left();
+        input.move_left();
+        assert!(input.delete_before());
+        assert_eq!(input.text(), "héllo wrld");
+        input.insert_char('ö');
+        assert_eq!(input.text(), "héllo wörld");
+
+        input.move_home();
+        input.move_right();
+        assert!(input.delete_after());
+        assert_eq!(input.text(), "hllo wörld");
+    }
+
+    #[test]
+    fn treats_grapheme_clusters_as_one_character() {
+        // "e" followed by a combining acute accent
+        let mut input = typed("cafe\u{301}!");
+        input.move_left();
+        input.move_left();
+        assert_eq!(input.cursor(), 3);
+        input.move_right();
+        assert_eq!(input.cursor(), "cafe\u{301}".len());
+        assert!(input.delete_before());
+        assert_eq!(input.text(), "caf!");
+
+        // Typing a base character in front of a combining mark joins them
+        let mut input = InputLine::default();
+        input.set_text("\u{301}x");
+        input.move_home();
+        input.insert_char('e');
+        assert_eq!(input.cursor(), "e\u{301}".len());
+    }
+
+    #[test]
+    fn measures_display_width() {
+        let input = typed("日本語");
+        assert_eq!(input.cursor_column(), 6);
+        let input = typed("e\u{301}x");
+        assert_eq!(input.cursor_column(), 2);
+    }
+
+    #[test]
+    fn scrolls_to_keep_the_cursor_visible() {
+        let mut input = typed("abcdefghij");
+        assert_eq!(input.view(5), ("ghij", 4));
+
+        input.move_home();
+        assert_eq!(input.view(5), ("abcde", 0));
+
+        // Moving right within the visible part doesn't scroll
+        input.move_right();
+        input.move_right();
+        assert_eq!(input.view(5), ("abcde", 2));
+
+        // Shrinking text scrolls back
+        input.set_text("abc");
+        assert_eq!(input.view(5), ("abc", 3));
+    }
+
+    #[test]
+    fn skips_wide_characters_cut_by_the_left_edge() {
+        let mut input = typed("日本語");
+        // Scrolled to column 3, which cuts 本 (columns 2-3) in half
+        assert_eq!(input.view(4), ("語", 2));
+    }
+}
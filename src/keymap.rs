@@ -0,0 +1,660 @@
+use crate::config::ViConfig;
+use crate::editor::Edit;
+use anyhow::{bail, Context, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Exit,
+    Launch,
+    CompleteTab,
+    SelectNext,
+    SelectPrev,
+    PageDown,
+    PageUp,
+    TogglePin,
+    Hide,
+    CycleMatcher,
+    ToggleExplain,
+    DeleteBefore,
+    DeleteAfter,
+    CursorLeft,
+    CursorRight,
+    LineStart,
+    LineEnd,
+    WordLeft,
+    WordRight,
+    KillWordBefore,
+    KillToWhitespace,
+    KillToStart,
+    KillToEnd,
+    Yank,
+    YankPop,
+    KillLine,
+    NormalMode,
+    Insert,
+    Append,
+    InsertAtStart,
+    AppendAtEnd,
+    SelectFirst,
+    SelectLast,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    CyclePathFilter,
+    ToggleHidden,
+    OpenWith,
+}
+
+/// Vi mode's input modes. Without vi mode every key is handled in insert
+/// mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMode {
+    Insert,
+    Normal,
+}
+
+/// Every action with its config name and description, in the order
+/// `exek --list-actions` prints them.
+const ACTIONS: &[(Action, &str, &str)] = &[
+    (Action::Exit, "exit", "Quit without launching anything"),
+    (Action::Launch, "launch", "Launch the selection, or enter the selected directory"),
+    (Action::CompleteTab, "complete-tab", "Complete the selected path"),
+    (Action::SelectNext, "select-next", "Select the next result"),
+    (Action::SelectPrev, "select-prev", "Select the previous result"),
+    (Action::PageDown, "page-down", "Select one page further down"),
+    (Action::PageUp, "page-up", "Select one page further up"),
+    (Action::TogglePin, "toggle-pin", "Pin or unpin the selected application"),
+    (Action::Hide, "hide", "Hide the selected application"),
+    (Action::CycleMatcher, "cycle-matcher", "Switch to the next matching algorithm"),
+    (Action::ToggleExplain, "toggle-explain", "Show or hide how scores are made up"),
+    (Action::DeleteBefore, "delete-before", "Delete the character before the cursor"),
+    (Action::DeleteAfter, "delete-after", "Delete the character under the cursor"),
+    (Action::CursorLeft, "cursor-left", "Move the cursor one character left"),
+    (Action::CursorRight, "cursor-right", "Move the cursor one character right"),
+    (Action::LineStart, "line-start", "Move the cursor to the start of the query"),
+    (Action::LineEnd, "line-end", "Move the cursor to the end of the query"),
+    (Action::WordLeft, "word-left", "Move to the start of the previous word"),
+    (Action::WordRight, "word-right", "Move to the end of the next word"),
+    (Action::KillWordBefore, "kill-word-before", "Delete the word before the cursor"),
+    (Action::KillToWhitespace, "kill-to-whitespace", "Delete back to the previous space"),
+    (Action::KillToStart, "kill-to-start", "Delete to the start of the query"),
+    (Action::KillToEnd, "kill-to-end", "Delete to the end of the query"),
+    (Action::Yank, "yank", "Insert the last deleted text"),
+    (Action::YankPop, "yank-pop", "Right after a yank, replace it with earlier deleted text"),
+    (Action::KillLine, "kill-line", "Delete the whole query"),
+    (Action::NormalMode, "normal-mode", "Vi mode: switch to normal mode"),
+    (Action::Insert, "insert", "Vi mode: insert before the cursor"),
+    (Action::Append, "append", "Vi mode: insert after the cursor"),
+    (Action::InsertAtStart, "insert-at-start", "Vi mode: insert at the start of the query"),
+    (Action::AppendAtEnd, "append-at-end", "Vi mode: insert at the end of the query"),
+    (Action::SelectFirst, "select-first", "Select the first result"),
+    (Action::SelectLast, "select-last", "Select the last result"),
+    (Action::HistoryPrev, "history-prev", "Recall the previous query from the history"),
+    (Action::HistoryNext, "history-next", "Recall the next query from the history"),
+    (Action::HistorySearch, "history-search", "Search past queries; again to cancel"),
+    (Action::CyclePathFilter, "cycle-path-filter", "List all files, executables or directories in path mode"),
+    (Action::ToggleHidden, "toggle-hidden", "Show or hide hidden files in path mode"),
+    (Action::OpenWith, "open-with", "Choose the application to open the selected file with"),
+];
+
+/// Bindings used unless the config file overrides them.
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("esc", Action::Exit),
+    ("ctrl-c", Action::Exit),
+    ("enter", Action::Launch),
+    ("tab", Action::CompleteTab),
+    ("down", Action::SelectNext),
+    ("ctrl-j", Action::SelectNext),
+    ("up", Action::SelectPrev),
+    ("pagedown", Action::PageDown),
+    ("pageup", Action::PageUp),
+    ("alt-p", Action::TogglePin),
+    ("alt-h", Action::Hide),
+    ("ctrl-s", Action::CycleMatcher),
+    ("alt-e", Action::ToggleExplain),
+    ("backspace", Action::DeleteBefore),
+    ("delete", Action::DeleteAfter),
+    ("left", Action::CursorLeft),
+    ("right", Action::CursorRight),
+    ("home", Action::LineStart),
+    ("ctrl-a", Action::LineStart),
+    ("end", Action::LineEnd),
+    ("ctrl-e", Action::LineEnd),
+    ("alt-b", Action::WordLeft),
+    ("ctrl-left", Action::WordLeft),
+    ("alt-f", Action::WordRight),
+    ("ctrl-right", Action::WordRight),
+    ("alt-backspace", Action::KillWordBefore),
+    ("ctrl-w", Action::KillToWhitespace),
+    ("ctrl-u", Action::KillToStart),
+    ("ctrl-k", Action::KillToEnd),
+    ("ctrl-y", Action::Yank),
+    ("alt-y", Action::YankPop),
+    ("ctrl-p", Action::HistoryPrev),
+    ("alt-up", Action::HistoryPrev),
+    ("ctrl-n", Action::HistoryNext),
+    ("alt-down", Action::HistoryNext),
+    ("ctrl-r", Action::HistorySearch),
+    ("alt-a", Action::CyclePathFilter),
+    ("alt-.", Action::ToggleHidden),
+    ("alt-enter", Action::OpenWith),
+];
+
+/// Insert mode bindings that differ in vi mode.
+const VI_INSERT_BINDINGS: &[(&str, Action)] = &[("esc", Action::NormalMode)];
+
+/// Normal mode bindings in vi mode.
+const VI_NORMAL_BINDINGS: &[(&str, Action)] = &[
+    ("esc", Action::Exit),
+    ("q", Action::Exit),
+    ("ctrl-c", Action::Exit),
+    ("enter", Action::Launch),
+    ("tab", Action::CompleteTab),
+    ("j", Action::SelectNext),
+    ("down", Action::SelectNext),
+    ("ctrl-j", Action::SelectNext),
+    ("k", Action::SelectPrev),
+    ("up", Action::SelectPrev),
+    ("ctrl-d", Action::PageDown),
+    ("pagedown", Action::PageDown),
+    ("ctrl-u", Action::PageUp),
+    ("pageup", Action::PageUp),
+    ("g g", Action::SelectFirst),
+    ("G", Action::SelectLast),
+    ("alt-p", Action::TogglePin),
+    ("alt-h", Action::Hide),
+    ("ctrl-s", Action::CycleMatcher),
+    ("alt-e", Action::ToggleExplain),
+    ("h", Action::CursorLeft),
+    ("left", Action::CursorLeft),
+    ("l", Action::CursorRight),
+    ("right", Action::CursorRight),
+    ("0", Action::LineStart),
+    ("home", Action::LineStart),
+    ("$", Action::LineEnd),
+    ("end", Action::LineEnd),
+    ("b", Action::WordLeft),
+    ("w", Action::WordRight),
+    ("e", Action::WordRight),
+    ("x", Action::DeleteAfter),
+    ("X", Action::DeleteBefore),
+    ("D", Action::KillToEnd),
+    ("d d", Action::KillLine),
+    ("p", Action::Yank),
+    ("i", Action::Insert),
+    ("a", Action::Append),
+    ("I", Action::InsertAtStart),
+    ("A", Action::AppendAtEnd),
+    ("ctrl-p", Action::HistoryPrev),
+    ("alt-up", Action::HistoryPrev),
+    ("ctrl-n", Action::HistoryNext),
+    ("alt-down", Action::HistoryNext),
+    ("ctrl-r", Action::HistorySearch),
+    ("alt-a", Action::CyclePathFilter),
+    ("alt-.", Action::ToggleHidden),
+    ("alt-enter", Action::OpenWith),
+];
+
+/// Value that removes a default binding in the `[keys]` table.
+const UNBOUND: &str = "none";
+
+impl Action {
+    pub fn all() -> impl Iterator<Item = Action> {
+        ACTIONS.iter().map(|(action, _, _)| *action)
+    }
+
+    pub fn name(self) -> &'static str {
+        self.entry().1
+    }
+
+    pub fn description(self) -> &'static str {
+        self.entry().2
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(_, action_name, _)| *action_name == name)
+            .map(|(action, _, _)| *action)
+    }
+
+    /// The edit this action makes to the search box, if it is an editing
+    /// action.
+    pub fn edit(self) -> Option<Edit> {
+        let edit = match self {
+            Action::DeleteBefore => Edit::DeleteBefore,
+            Action::DeleteAfter => Edit::DeleteAfter,
+            Action::CursorLeft => Edit::Left,
+            Action::CursorRight => Edit::Right,
+            Action::LineStart => Edit::Home,
+            Action::LineEnd => Edit::End,
+            Action::WordLeft => Edit::WordLeft,
+            Action::WordRight => Edit::WordRight,
+            Action::KillWordBefore => Edit::KillWordBefore,
+            Action::KillToWhitespace => Edit::KillToWhitespace,
+            Action::KillToStart => Edit::KillToStart,
+            Action::KillToEnd => Edit::KillToEnd,
+            Action::Yank => Edit::Yank,
+            Action::YankPop => Edit::YankPop,
+            Action::KillLine => Edit::KillLine,
+            _ => return None,
+        };
+        Some(edit)
+    }
+
+    fn entry(self) -> &'static (Action, &'static str, &'static str) {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .expect("every action is listed in ACTIONS")
+    }
+}
+
+/// A key with its modifiers, written like `ctrl-a`, `alt-backspace` or
+/// `G`. Shift is part of the character for printable keys, so `shift-g`
+/// and `G` are the same chord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    /// The character this chord types into the search box when it isn't
+    /// bound to anything.
+    pub fn printable(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+/// Names of keys without a printable character, as written in the config.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("space", KeyCode::Char(' ')),
+];
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = value;
+        // A trailing `-` is the minus key itself, as in `alt--`
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier `{}` in `{}`", modifier, value),
+            };
+            rest = key;
+        }
+
+        let lower = rest.to_lowercase();
+        let code = if let Some((_, code)) = KEY_NAMES.iter().find(|(name, _)| *name == lower) {
+            *code
+        } else if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            KeyCode::F(number)
+        } else {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                    KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+                }
+                (Some(c), None) => KeyCode::Char(c),
+                _ => bail!("unknown key `{}` in `{}`", rest, value),
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(number) => write!(f, "f{}", number),
+            code => match KEY_NAMES.iter().find(|(_, named)| *named == code) {
+                Some((name, _)) => write!(f, "{}", name),
+                None => write!(f, "{:?}", code),
+            },
+        }
+    }
+}
+
+/// A key sequence as written in the config: chords separated by spaces,
+/// e.g. `ctrl-x ctrl-c` or `g g`.
+fn parse_sequence(value: &str) -> Result<Vec<KeyChord>> {
+    let sequence = value
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<KeyChord>>>()?;
+    if sequence.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(sequence)
+}
+
+fn format_sequence(sequence: &[KeyChord]) -> String {
+    sequence.iter().map(KeyChord::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// What a key press resolved to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyResult {
+    Action(Action),
+    /// The keys so far start a longer binding; wait for the next one
+    Pending,
+    /// The keys typed since the last action match no binding
+    Unbound(Vec<KeyChord>),
+}
+
+type Bindings = HashMap<Vec<KeyChord>, Action>;
+
+/// Maps key sequences to actions, feeding key presses one at a time so
+/// multi-key sequences can be bound.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    insert: Bindings,
+    /// Empty unless vi mode is on
+    normal: Bindings,
+    /// Keys typed so far of an unfinished sequence
+    pending: Vec<KeyChord>,
+}
+
+impl Keymap {
+    /// Builds the default bindings with `overrides` from the `[keys]`
+    /// table applied, plus the normal mode bindings when vi mode is on.
+    /// Overrides map a key sequence to an action name, or to `none` to
+    /// remove a default binding.
+    pub fn new(overrides: &BTreeMap<String, String>, vi: &ViConfig) -> Result<Self> {
+        let mut insert = defaults(DEFAULT_BINDINGS)?;
+        let mut normal = Bindings::new();
+        if vi.enabled {
+            insert.extend(defaults(VI_INSERT_BINDINGS)?);
+            normal = defaults(VI_NORMAL_BINDINGS)?;
+            apply_overrides(&mut normal, &vi.keys).context("In [vi.keys]")?;
+        }
+        apply_overrides(&mut insert, overrides)?;
+
+        Ok(Self { insert, normal, pending: Vec::new() })
+    }
+
+    /// Resolves the next key press in `mode`.
+    pub fn feed(&mut self, mode: KeyMode, chord: KeyChord) -> KeyResult {
+        let bindings = match mode {
+            KeyMode::Insert => &self.insert,
+            KeyMode::Normal => &self.normal,
+        };
+        self.pending.push(chord);
+        if let Some(action) = bindings.get(&self.pending) {
+            self.pending.clear();
+            return KeyResult::Action(*action);
+        }
+        if bindings.keys().any(|sequence| sequence.starts_with(&self.pending)) {
+            return KeyResult::Pending;
+        }
+        KeyResult::Unbound(std::mem::take(&mut self.pending))
+    }
+
+    /// The keys typed so far of an unfinished sequence, e.g. `ctrl-x`.
+    pub fn pending(&self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| format_sequence(&self.pending))
+    }
+
+    /// Whether vi mode's normal mode has bindings.
+    pub fn has_normal_mode(&self) -> bool {
+        !self.normal.is_empty()
+    }
+
+    /// The key sequences bound to `action` in `mode`, sorted.
+    pub fn keys_for(&self, mode: KeyMode, action: Action) -> Vec<String> {
+        let bindings = match mode {
+            KeyMode::Insert => &self.insert,
+            KeyMode::Normal => &self.normal,
+        };
+        let mut keys: Vec<String> = bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(sequence, _)| format_sequence(sequence))
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+fn defaults(bindings: &[(&str, Action)]) -> Result<Bindings> {
+    bindings
+        .iter()
+        .map(|(keys, action)| Ok((parse_sequence(keys)?, *action)))
+        .collect()
+}
+
+fn apply_overrides(bindings: &mut Bindings, overrides: &BTreeMap<String, String>) -> Result<()> {
+    // Different spellings of the same keys, like `G` and `shift-g`
+    let mut spellings: HashMap<Vec<KeyChord>, &str> = HashMap::new();
+    for (keys, name) in overrides {
+        let sequence = parse_sequence(keys).with_context(|| format!("Invalid key binding `{}`", keys))?;
+        if let Some(other) = spellings.insert(sequence.clone(), keys) {
+            bail!("Key bindings `{}` and `{}` are the same keys", other, keys);
+        }
+
+        if name == UNBOUND {
+            bindings.remove(&sequence);
+        } else {
+            let action = Action::from_name(name).with_context(|| {
+                format!("Unknown action `{}` for `{}`; see `exek --list-actions`", name, keys)
+            })?;
+            bindings.insert(sequence, action);
+        }
+    }
+    check_conflicts(bindings)
+}
+
+/// Rejects a binding that starts another one, since the longer one could
+/// never be typed.
+fn check_conflicts(bindings: &Bindings) -> Result<()> {
+    let mut sequences: Vec<&Vec<KeyChord>> = bindings.keys().collect();
+    sequences.sort_by_key(|sequence| format_sequence(sequence));
+    for short in &sequences {
+        for long in &sequences {
+            if long.len() > short.len() && long.starts_with(short) {
+                bail!(
+                    "Key binding `{}` ({}) conflicts with `{}` ({}); set one of them to \"{}\"",
+                    format_sequence(short),
+                    bindings[*short].name(),
+                    format_sequence(long),
+                    bindings[*long].name(),
+                    UNBOUND,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints every action with its current bindings, for `exek --list-actions`.
+/// Normal mode bindings get their own column when vi mode is on.
+pub fn print_actions(keymap: &Keymap) {
+    let mut modes = vec![(KeyMode::Insert, "KEYS")];
+    if keymap.has_normal_mode() {
+        modes = vec![(KeyMode::Insert, "INSERT KEYS"), (KeyMode::Normal, "NORMAL KEYS")];
+    }
+
+    let mut header = vec!["ACTION".to_string()];
+    header.extend(modes.iter().map(|(_, title)| title.to_string()));
+    header.push("DESCRIPTION".to_string());
+
+    let mut table = vec![header];
+    for action in Action::all() {
+        let mut row = vec![action.name().to_string()];
+        for (mode, _) in &modes {
+            let keys = keymap.keys_for(*mode, action);
+            row.push(if keys.is_empty() { "-".to_string() } else { keys.join(", ") });
+        }
+        row.push(action.description().to_string());
+        table.push(row);
+    }
+
+    let mut widths = vec![0; table[0].len()];
+    for row in &table {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in &table {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(value: &str) -> KeyChord {
+        value.parse().unwrap()
+    }
+
+    fn table(overrides: &[(&str, &str)]) -> BTreeMap<String, String> {
+        overrides
+            .iter()
+            .map(|(keys, action)| (keys.to_string(), action.to_string()))
+            .collect()
+    }
+
+    fn keymap(overrides: &[(&str, &str)]) -> Result<Keymap> {
+        Keymap::new(&table(overrides), &ViConfig::default())
+    }
+
+    #[test]
+    fn parses_chords() {
+        let key = |code, modifiers| KeyChord::from(KeyEvent::new(code, modifiers));
+        assert_eq!(chord("ctrl-a"), key(KeyCode::Char('a'), KeyModifiers::CONTROL));
+        assert_eq!(chord("Alt-Backspace"), key(KeyCode::Backspace, KeyModifiers::ALT));
+        assert_eq!(chord("shift-g"), key(KeyCode::Char('G'), KeyModifiers::SHIFT));
+        assert_eq!(chord("G"), chord("shift-g"));
+        assert_eq!(chord("alt--"), key(KeyCode::Char('-'), KeyModifiers::ALT));
+        assert_eq!(chord("f5"), key(KeyCode::F(5), KeyModifiers::NONE));
+        assert_eq!(chord("ctrl-alt-space").to_string(), "ctrl-alt-space");
+        assert!("hyper-a".parse::<KeyChord>().is_err());
+        assert!("ctrl-enterr".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn maps_default_keys() {
+        let mut keymap = keymap(&[]).unwrap();
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("ctrl-a")), KeyResult::Action(Action::LineStart));
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("alt-backspace")), KeyResult::Action(Action::KillWordBefore));
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("F")), KeyResult::Unbound(vec![chord("F")]));
+        assert_eq!(chord("F").printable(), Some('F'));
+        assert_eq!(chord("ctrl-x").printable(), None);
+    }
+
+    #[test]
+    fn applies_overrides_and_sequences() {
+        let mut keymap = keymap(&[
+            ("ctrl-k", "select-prev"),
+            ("ctrl-x ctrl-c", "exit"),
+            ("esc", "none"),
+        ])
+        .unwrap();
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("ctrl-k")), KeyResult::Action(Action::SelectPrev));
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("esc")), KeyResult::Unbound(vec![chord("esc")]));
+
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("ctrl-x")), KeyResult::Pending);
+        assert_eq!(keymap.pending().as_deref(), Some("ctrl-x"));
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("ctrl-c")), KeyResult::Action(Action::Exit));
+        assert_eq!(keymap.pending(), None);
+
+        // A key that doesn't continue the sequence ends it
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("ctrl-x")), KeyResult::Pending);
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("a")), KeyResult::Unbound(vec![chord("ctrl-x"), chord("a")]));
+
+        assert_eq!(keymap.keys_for(KeyMode::Insert, Action::Exit), ["ctrl-c", "ctrl-x ctrl-c"]);
+    }
+
+    #[test]
+    fn detects_conflicts() {
+        // A prefix of a default binding
+        let error = keymap(&[("ctrl-a x", "exit")]).unwrap_err();
+        assert!(error.to_string().contains("`ctrl-a` (line-start) conflicts with `ctrl-a x` (exit)"));
+        assert!(keymap(&[("ctrl-a x", "exit"), ("ctrl-a", "none")]).is_ok());
+
+        // Two spellings of the same keys
+        assert!(keymap(&[("G", "exit"), ("shift-g", "launch")]).is_err());
+
+        assert!(keymap(&[("ctrl-q", "explode")]).is_err());
+    }
+
+    #[test]
+    fn vi_mode_has_normal_mode_bindings() {
+        let vi = ViConfig {
+            enabled: true,
+            keys: table(&[("q", "none")]),
+        };
+        let mut keymap = Keymap::new(&BTreeMap::new(), &vi).unwrap();
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("esc")), KeyResult::Action(Action::NormalMode));
+        assert_eq!(keymap.feed(KeyMode::Insert, chord("j")), KeyResult::Unbound(vec![chord("j")]));
+
+        assert_eq!(keymap.feed(KeyMode::Normal, chord("j")), KeyResult::Action(Action::SelectNext));
+        assert_eq!(keymap.feed(KeyMode::Normal, chord("g")), KeyResult::Pending);
+        assert_eq!(keymap.feed(KeyMode::Normal, chord("g")), KeyResult::Action(Action::SelectFirst));
+        assert_eq!(keymap.feed(KeyMode::Normal, chord("shift-g")), KeyResult::Action(Action::SelectLast));
+        assert_eq!(keymap.feed(KeyMode::Normal, chord("q")), KeyResult::Unbound(vec![chord("q")]));
+
+        let error = Keymap::new(&BTreeMap::new(), &ViConfig { enabled: true, keys: table(&[("d", "exit")]) });
+        assert!(format!("{:#}", error.unwrap_err()).contains("In [vi.keys]"));
+    }
+}
@@ -0,0 +1,20 @@
+pub mod cli;
+pub mod config;
+pub mod database;
+pub mod desktop_entry;
+pub mod editor;
+pub mod history;
+pub mod importers;
+pub mod input;
+pub mod keymap;
+pub mod matcher;
+pub mod mime;
+pub mod mimeapps;
+pub mod normalize;
+pub mod open_with;
+pub mod path_completion;
+pub mod query;
+pub mod query_history;
+pub mod search;
+pub mod ui;
+pub mod worker;
@@ -1,35 +1,72 @@
-mod database;
-mod desktop_entry;
-mod path_completion;
-mod search;
-mod ui;
-
 use anyhow::Result;
+use clap::Parser;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use database::Database;
-use desktop_entry::DesktopScanner;
-use path_completion::PathCompleter;
+use exek::cli::{Cli, Command as CliCommand};
+use exek::config::Config;
+use exek::database::Database;
+use exek::desktop_entry::{AppEntry, DesktopScanner};
+use exek::editor::Edit;
+use exek::history;
+use exek::keymap::{self, Action, KeyChord, KeyMode, KeyResult, Keymap};
+use exek::matcher::CaseMode;
+use exek::mime::MimeDatabase;
+use exek::mimeapps::MimeApps;
+use exek::open_with::OpenWith;
+use exek::path_completion::{self, PathCompleter};
+use exek::query_history::QueryHistory;
+use exek::search::Searcher;
+use exek::ui::{self, AppState, SearchMode};
+use exek::worker::SearchWorker;
 use ratatui::{
     backend::CrosstermBackend,
     Terminal,
 };
-use search::Searcher;
 use std::env;
 use std::io;
+use std::path::Path;
 use std::process::Command;
-use ui::{AppState, SearchMode};
+use std::time::Duration;
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if let Some(command) = cli.command {
+        let result = match command {
+            CliCommand::History(command) => history::run(command),
+            CliCommand::Pin(args) => history::set_pinned(args.id, true),
+            CliCommand::Unpin(args) => history::set_pinned(args.id, false),
+            CliCommand::Hide(args) => history::set_hidden(args.id, true),
+            CliCommand::Unhide(args) => history::set_hidden(args.id, false),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if cli.list_actions {
+        let result = Config::load().and_then(|config| Keymap::new(&config.keys, &config.vi));
+        match result {
+            Ok(keymap) => keymap::print_actions(&keymap),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
     let mut terminal = setup_terminal()?;
-    let result = run_app(&mut terminal);
+    let result = run_app(&mut terminal, cli.explain);
     restore_terminal(&mut terminal)?;
 
     if let Err(e) = result {
-        eprintln!("Error: {}", e);
+        eprintln!("Error: {:#}", e);
     }
 
     Ok(())
@@ -54,8 +91,13 @@ fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Re
     Ok(())
 }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
-    let mut apps = DesktopScanner::scan()?;
+fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, explain: bool) -> Result<()> {
+    let config = Config::load()?;
+    let mut keymap = Keymap::new(&config.keys, &config.vi)?;
+    // NoDisplay entries can only be chosen to open files
+    let handlers = DesktopScanner::scan_all()?;
+    let mut apps: Vec<AppEntry> = handlers.iter().filter(|app| !app.no_display).cloned().collect();
+    apps.extend(config.custom_apps());
     let mut database = Database::load().unwrap_or_else(|_| Database::new());
 
     // Add frequently used paths as synthetic app entries
@@ -64,154 +106,357 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
             let path_buf = std::path::PathBuf::from(&path_str);
             // Try both with and without canonicalization
             if path_buf.exists() {
-                apps.push(desktop_entry::AppEntry::from_path(&path_buf));
+                apps.push(AppEntry::from_path(&path_buf));
             } else if let Ok(path) = path_buf.canonicalize() {
                 if path.exists() {
-                    apps.push(desktop_entry::AppEntry::from_path(&path));
+                    apps.push(AppEntry::from_path(&path));
                 }
             }
         }
     }
-    let searcher = Searcher::new(database.clone());
-    let path_completer = PathCompleter::new();
-    let mut state = AppState::new();
+    if database.migrate(&apps) {
+        database.save()?;
+    }
 
-    state.mode = SearchMode::Applications(searcher.search("", &apps));
+    let mut history = QueryHistory::load().unwrap_or_default();
 
-    loop {
-        terminal.draw(|frame| ui::render(frame, &state))?;
+    let mut searcher = Searcher::new(apps, database.clone(), &config.search);
+    searcher.set_explain(explain);
+    let mut path_completer = PathCompleter::new(config.search.matcher, config.search.case);
+    path_completer.set_filter(config.paths.show);
+    path_completer.set_show_hidden(config.paths.hidden);
+    path_completer.set_ignore_files(config.paths.ignore_files);
+    let mut worker = SearchWorker::new(searcher, path_completer)?;
+    let mut state = AppState::new(config.search.matcher);
+    state.path_filter = config.paths.show;
+    state.show_hidden = config.paths.hidden;
+    state.explain = explain;
+    state.vi_mode = config.vi.enabled.then_some(KeyMode::Insert);
 
+    worker.request("");
+
+    loop {
         // Calculate visible height for scrolling adjustments
         let visible_height = terminal.size()?.height.saturating_sub(5) as usize;
 
+        // Searches started while the history or the applications for a
+        // file are listed are superseded when they close
+        if let Some(mode) = worker.try_recv().filter(|_| !state.is_choosing()) {
+            state.mode = mode;
+            state.clamp_selection(visible_height);
+        }
+        state.searching = worker.is_searching() && !state.is_choosing();
+        state.pending_keys = keymap.pending();
+        state.matcher_key = keymap.keys_for(state.key_mode(), Action::CycleMatcher).into_iter().next();
+
+        terminal.draw(|frame| ui::render(frame, &mut state, &config.theme))?;
+
+        // Only wake up for results while a search is running
+        if state.searching && !event::poll(Duration::from_millis(16))? {
+            continue;
+        }
+
         if let Event::Key(key) = event::read()? {
-            match (key.code, key.modifiers) {
-                (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                    return Ok(());
+            let action = match keymap.feed(state.key_mode(), KeyChord::from(key)) {
+                KeyResult::Action(action) => action,
+                KeyResult::Pending => continue,
+                // Normal mode doesn't type
+                KeyResult::Unbound(_) if state.key_mode() == KeyMode::Normal => continue,
+                KeyResult::Unbound(chords) => {
+                    let mut changed = false;
+                    for c in chords.iter().filter_map(KeyChord::printable) {
+                        changed |= state.editor.apply(&mut state.input, Edit::Insert(c));
+                    }
+                    if changed {
+                        refresh(&mut state, &mut worker, &history, config.search.case);
+                    }
+                    continue;
                 }
-                (KeyCode::Tab, _) => {
-                    if let SearchMode::Paths(completions) = &state.mode {
-                        if let Some(selected) = completions.get(state.selected_index) {
-                            state.query = path_completion::PathCompleter::apply_completion(&state.query, selected);
-                            state.cursor_position = state.query.len();
-                            let new_completions = path_completer.complete_path(&state.query);
-                            state.mode = SearchMode::Paths(new_completions);
-                            state.reset_selection();
+            };
+
+            if state.open_with.is_some()
+                && matches!(action, Action::Launch | Action::CompleteTab | Action::Exit | Action::OpenWith)
+            {
+                match action {
+                    Action::Launch => {
+                        if let (Some(open_with), Some(selected)) = (&state.open_with, state.get_selected_handler()) {
+                            history.record(&open_with.query);
+                            history.save()?;
+                            database.record_path_launch(&open_with.file)?;
+                            database.record_handler_launch(&open_with.mime, &selected.app.id)?;
+                            return launch_app(&selected.app, Some(&open_with.file));
                         }
                     }
+                    // Nothing to complete
+                    Action::CompleteTab => {}
+                    // Go back to the path
+                    _ => {
+                        if let Some(open_with) = state.open_with.take() {
+                            state.input.set_text(open_with.query);
+                        }
+                        refresh(&mut state, &mut worker, &history, config.search.case);
+                    }
+                }
+                continue;
+            }
+
+            if state.history_search.is_some()
+                && matches!(action, Action::Launch | Action::CompleteTab | Action::Exit | Action::HistorySearch)
+            {
+                // Take the selected query, or go back to the one typed before
+                let selected = match action {
+                    Action::Launch | Action::CompleteTab => {
+                        state.get_selected_history().map(|found| found.query.clone())
+                    }
+                    _ => None,
+                };
+                let typed = state.history_search.take().unwrap_or_default();
+                state.input.set_text(selected.unwrap_or(typed));
+                refresh(&mut state, &mut worker, &history, config.search.case);
+                continue;
+            }
+
+            // Act on the results for the query as typed, not older ones
+            if matches!(action, Action::Launch | Action::CompleteTab | Action::OpenWith) && !state.is_choosing() {
+                if let Some(mode) = worker.wait() {
+                    state.mode = mode;
+                    state.clamp_selection(visible_height);
+                }
+            }
+
+            match action {
+                Action::Exit => {
+                    return Ok(());
                 }
-                (KeyCode::Enter, _) => {
+                Action::CompleteTab => {
+                    if let Some(selected) = state.get_selected_path() {
+                        let completed = PathCompleter::apply_completion(state.input.text(), selected);
+                        state.input.set_text(completed);
+                        refresh(&mut state, &mut worker, &history, config.search.case);
+                    }
+                }
+                Action::Launch => {
                     match &state.mode {
                         SearchMode::Applications(_) => {
                             if let Some(selected) = state.get_selected_app() {
-                                database.record_launch(&selected.app.name)?;
-                                launch_app(&selected.app)?;
+                                history.record(state.input.text());
+                                history.save()?;
+                                database.record_launch(&selected.app.id)?;
+                                // Files opened from path mode before
+                                match selected.app.id.strip_prefix("path:").map(Path::new) {
+                                    Some(path) if !path_completion::is_executable(path) => open_file(path, &handlers)?,
+                                    _ => launch_app(&selected.app, None)?,
+                                }
                                 return Ok(());
                             }
                         }
                         SearchMode::Paths(completions) => {
                             if let Some(selected) = completions.get(state.selected_index) {
                                 if selected.is_dir {
-                                    state.query = path_completion::PathCompleter::apply_completion(&state.query, selected);
-                                    state.cursor_position = state.query.len();
-                                    let new_completions = path_completer.complete_path(&state.query);
-                                    state.mode = SearchMode::Paths(new_completions);
-                                    state.reset_selection();
+                                    let completed = PathCompleter::apply_completion(state.input.text(), selected);
+                                    state.input.set_text(completed);
+                                    refresh(&mut state, &mut worker, &history, config.search.case);
                                 } else {
+                                    history.record(state.input.text());
+                                    history.save()?;
                                     // Record the launch in the database with the full path
                                     database.record_path_launch(&selected.path)?;
-                                    launch_executable(&selected.path)?;
+                                    if selected.is_executable {
+                                        launch_executable(&selected.path)?;
+                                    } else {
+                                        open_file(&selected.path, &handlers)?;
+                                    }
                                     return Ok(());
                                 }
                             }
                         }
+                        SearchMode::History(_) | SearchMode::OpenWith(_) => {}
+                    }
+                }
+                Action::TogglePin => {
+                    if let Some(selected) = state.get_selected_app() {
+                        let id = selected.app.id.clone();
+                        let pinned = !database.is_pinned(&id);
+                        database.set_pinned(&id, pinned);
+                        database.save()?;
+                        let database = database.clone();
+                        worker.update_searcher(move |searcher| searcher.set_database(database));
+                        worker.request(state.input.text());
+                    }
+                }
+                Action::Hide => {
+                    if let Some(selected) = state.get_selected_app() {
+                        let id = selected.app.id.clone();
+                        database.set_hidden(&id, true);
+                        database.save()?;
+                        let database = database.clone();
+                        worker.update_searcher(move |searcher| searcher.set_database(database));
+                        worker.request(state.input.text());
                     }
                 }
-                (KeyCode::Up, _) | (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                Action::CycleMatcher => {
+                    state.matcher = state.matcher.next();
+                    let matcher = state.matcher;
+                    worker.update_searcher(move |searcher| searcher.set_matcher(matcher));
+                    worker.update_path_completer(|completer| completer.set_matcher(state.matcher));
+                    refresh(&mut state, &mut worker, &history, config.search.case);
+                }
+                Action::ToggleExplain => {
+                    state.explain = !state.explain;
+                    let explain = state.explain;
+                    worker.update_searcher(move |searcher| searcher.set_explain(explain));
+                    worker.request(state.input.text());
+                }
+                Action::SelectPrev => {
                     state.move_selection_up();
                     state.adjust_scroll(visible_height);
                 }
-                (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                Action::SelectNext => {
                     state.move_selection_down();
                     state.adjust_scroll(visible_height);
                 }
-                (KeyCode::PageUp, _) => {
-                    state.move_selection_page_up(visible_height);
+                Action::SelectFirst => {
+                    state.move_selection_first();
                     state.adjust_scroll(visible_height);
                 }
-                (KeyCode::PageDown, _) => {
-                    state.move_selection_page_down(visible_height);
+                Action::SelectLast => {
+                    state.move_selection_last();
                     state.adjust_scroll(visible_height);
                 }
-                (KeyCode::Backspace, _) => {
-                    if state.cursor_position > 0 {
-                        let remove_pos = state.cursor_position - 1;
-                        state.query.remove(remove_pos);
-                        state.cursor_position -= 1;
-                        update_search_mode(&mut state, &searcher, &path_completer, &apps);
-                        state.reset_selection();
+                Action::NormalMode => {
+                    if state.vi_mode.is_some() {
+                        state.vi_mode = Some(KeyMode::Normal);
+                        // Like vi, leave the cursor on the last typed character
+                        state.editor.apply(&mut state.input, Edit::Left);
                     }
                 }
-                (KeyCode::Delete, _) => {
-                    if state.cursor_position < state.query.len() {
-                        state.query.remove(state.cursor_position);
-                        update_search_mode(&mut state, &searcher, &path_completer, &apps);
-                        state.reset_selection();
+                Action::Insert | Action::Append | Action::InsertAtStart | Action::AppendAtEnd => {
+                    let motion = match action {
+                        Action::Append => Some(Edit::Right),
+                        Action::InsertAtStart => Some(Edit::Home),
+                        Action::AppendAtEnd => Some(Edit::End),
+                        _ => None,
+                    };
+                    if let Some(motion) = motion {
+                        state.editor.apply(&mut state.input, motion);
+                    }
+                    if state.vi_mode.is_some() {
+                        state.vi_mode = Some(KeyMode::Insert);
                     }
                 }
-                (KeyCode::Left, _) => {
-                    if state.cursor_position > 0 {
-                        state.cursor_position -= 1;
+                Action::HistoryPrev | Action::HistoryNext if state.is_choosing() => {
+                    // Move through the listed queries, as in fzf
+                    if action == Action::HistoryPrev {
+                        state.move_selection_up();
+                    } else {
+                        state.move_selection_down();
                     }
+                    state.adjust_scroll(visible_height);
                 }
-                (KeyCode::Right, _) => {
-                    if state.cursor_position < state.query.len() {
-                        state.cursor_position += 1;
+                Action::HistoryPrev | Action::HistoryNext => {
+                    let recalled = match action {
+                        Action::HistoryPrev => history.previous(state.input.text()),
+                        _ => history.next(state.input.text()),
+                    };
+                    if let Some(query) = recalled.map(str::to_string) {
+                        state.input.set_text(query);
+                        refresh(&mut state, &mut worker, &history, config.search.case);
                     }
                 }
-                (KeyCode::Home, _) => {
-                    state.cursor_position = 0;
+                Action::CyclePathFilter => {
+                    state.path_filter = state.path_filter.next();
+                    worker.update_path_completer(|completer| completer.set_filter(state.path_filter));
+                    refresh(&mut state, &mut worker, &history, config.search.case);
+                }
+                Action::ToggleHidden => {
+                    state.show_hidden = !state.show_hidden;
+                    worker.update_path_completer(|completer| completer.set_show_hidden(state.show_hidden));
+                    refresh(&mut state, &mut worker, &history, config.search.case);
                 }
-                (KeyCode::End, _) => {
-                    state.cursor_position = state.query.len();
+                Action::HistorySearch if state.open_with.is_none() => {
+                    state.history_search = Some(state.input.text().to_string());
+                    refresh(&mut state, &mut worker, &history, config.search.case);
                 }
-                (KeyCode::Char(c), _) => {
-                    state.query.insert(state.cursor_position, c);
-                    state.cursor_position += 1;
-                    update_search_mode(&mut state, &searcher, &path_completer, &apps);
-                    state.reset_selection();
+                Action::OpenWith => {
+                    if let Some(selected) = state.get_selected_path().filter(|selected| !selected.is_dir) {
+                        let mime = MimeDatabase::load();
+                        let open_with = OpenWith::new(
+                            selected.path.clone(),
+                            &file_types(&mime, &selected.path),
+                            state.input.text().to_string(),
+                            &MimeApps::load(),
+                            &mime,
+                            &handlers,
+                            &database,
+                        );
+                        state.open_with = Some(open_with);
+                        // The query now filters the applications
+                        state.input.set_text("");
+                        refresh(&mut state, &mut worker, &history, config.search.case);
+                    }
+                }
+                Action::PageUp => {
+                    state.move_selection_page_up(visible_height);
+                    state.adjust_scroll(visible_height);
+                }
+                Action::PageDown => {
+                    state.move_selection_page_down(visible_height);
+                    state.adjust_scroll(visible_height);
+                }
+                _ => {
+                    if let Some(edit) = action.edit() {
+                        if state.editor.apply(&mut state.input, edit) {
+                            refresh(&mut state, &mut worker, &history, config.search.case);
+                        }
+                    }
                 }
-                _ => {}
             }
         }
     }
 }
 
-fn update_search_mode(
-    state: &mut AppState,
-    searcher: &Searcher,
-    path_completer: &PathCompleter,
-    apps: &[desktop_entry::AppEntry],
-) {
-    if path_completion::PathCompleter::is_path_query(&state.query) {
-        let completions = path_completer.complete_path(&state.query);
-        state.mode = SearchMode::Paths(completions);
+/// Shows results for the query in the search box: matching applications
+/// while choosing one to open a file with, matching past queries while
+/// searching the history, otherwise applications or paths.
+fn refresh(state: &mut AppState, worker: &mut SearchWorker, history: &QueryHistory, case: CaseMode) {
+    if let Some(open_with) = &state.open_with {
+        let matcher = state.matcher.build();
+        state.mode = SearchMode::OpenWith(open_with.search(state.input.text(), matcher.as_ref(), case));
+    } else if state.history_search.is_some() {
+        let matcher = state.matcher.build();
+        state.mode = SearchMode::History(history.search(state.input.text(), matcher.as_ref(), case));
     } else {
-        let results = searcher.search(&state.query, apps);
-        state.mode = SearchMode::Applications(results);
+        worker.request(state.input.text());
     }
+    state.reset_selection();
 }
 
-fn launch_app(app: &desktop_entry::AppEntry) -> Result<()> {
-    let cmd = app.get_launch_command();
-    let parts: Vec<&str> = cmd.split_whitespace().collect();
+/// Returns the type of the file at `path`, followed by the broader types
+/// it is a subclass of.
+fn file_types(mime: &MimeDatabase, path: &Path) -> Vec<String> {
+    mime.ancestors(&mime.guess(path))
+}
+
+/// Opens `path` with the default application for its type.
+fn open_file(path: &Path, apps: &[AppEntry]) -> Result<()> {
+    let mime = MimeDatabase::load();
+    let mime_types = file_types(&mime, path);
+    let handler = MimeApps::load()
+        .default_handler(&mime_types, apps, &mime)
+        .ok_or_else(|| anyhow::anyhow!("No application to open {} ({})", path.display(), mime_types[0]))?;
+    launch_app(handler, Some(path))
+}
+
+/// Launches `app`, passing it `file` to open if given.
+fn launch_app(app: &AppEntry, file: Option<&Path>) -> Result<()> {
+    let parts = app.get_launch_args_for(file);
 
     if parts.is_empty() {
         return Err(anyhow::anyhow!("Empty command"));
     }
 
     // Resolve the executable to its absolute path if possible
-    let executable = parts[0];
+    let executable = parts[0].as_str();
     let resolved_executable = if executable.contains('/') {
         // Already a path, expand it
         shellexpand::tilde(executable).to_string()
@@ -239,7 +484,7 @@ fn launch_app(app: &desktop_entry::AppEntry) -> Result<()> {
                 command = Command::new(term_path);
                 ensure_environment(&mut command);
                 command.arg("-e");
-                command.arg(&cmd);
+                command.arg(parts.iter().map(|part| shell_quote(part)).collect::<Vec<_>>().join(" "));
                 break;
             }
         }
@@ -263,6 +508,15 @@ fn launch_app(app: &desktop_entry::AppEntry) -> Result<()> {
     Ok(())
 }
 
+/// Quotes `arg` for a POSIX shell, for terminals that take the command as
+/// one string.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
 fn ensure_environment(command: &mut Command) {
     // Ensure PATH includes common directories
     let path = env::var("PATH").unwrap_or_else(|_| String::new());
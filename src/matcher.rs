@@ -0,0 +1,358 @@
+use fuzzy_matcher::clangd::ClangdMatcher as ClangdFuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::sync::RwLock;
+
+/// Matches a single pattern against a field, returning the score and the
+/// char indices of the matched characters.
+pub trait Matcher: Send + Sync {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)>;
+
+    /// Whether appending to a pattern can only lose matches, so the matches
+    /// of a longer pattern are always among those of its prefix.
+    fn narrows(&self) -> bool {
+        true
+    }
+}
+
+/// Whether query terms match case-sensitively.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    /// Case-sensitive only for terms containing uppercase letters
+    #[default]
+    Smart,
+    Ignore,
+    Respect,
+}
+
+impl CaseMode {
+    pub fn is_sensitive(&self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Smart => pattern.chars().any(|c| c.is_uppercase()),
+            CaseMode::Ignore => false,
+            CaseMode::Respect => true,
+        }
+    }
+}
+
+/// The matching algorithm used for plain query terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatcherKind {
+    /// skim's fuzzy matching (the default)
+    #[default]
+    Skim,
+    /// clangd-style fuzzy matching, stricter about word boundaries
+    Clangd,
+    /// Case-insensitive substring
+    Substring,
+    /// Field starts with the pattern
+    Prefix,
+    /// Pattern characters match word initials, e.g. `vsc` for "Visual Studio Code"
+    Acronym,
+    /// Regular expression
+    Regex,
+}
+
+impl MatcherKind {
+    const ALL: [MatcherKind; 6] = [
+        MatcherKind::Skim,
+        MatcherKind::Clangd,
+        MatcherKind::Substring,
+        MatcherKind::Prefix,
+        MatcherKind::Acronym,
+        MatcherKind::Regex,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatcherKind::Skim => "skim",
+            MatcherKind::Clangd => "clangd",
+            MatcherKind::Substring => "substring",
+            MatcherKind::Prefix => "prefix",
+            MatcherKind::Acronym => "acronym",
+            MatcherKind::Regex => "regex",
+        }
+    }
+
+    /// Returns the next kind, wrapping around, for cycling at runtime.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|kind| kind == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn build(&self) -> Box<dyn Matcher> {
+        match self {
+            MatcherKind::Skim => Box::new(SkimMatcher {
+                respect_case: SkimMatcherV2::default().respect_case(),
+                ignore_case: SkimMatcherV2::default().ignore_case(),
+            }),
+            MatcherKind::Clangd => Box::new(ClangdMatcher {
+                respect_case: ClangdFuzzyMatcher::default().respect_case(),
+                ignore_case: ClangdFuzzyMatcher::default().ignore_case(),
+            }),
+            MatcherKind::Substring => Box::new(SubstringMatcher),
+            MatcherKind::Prefix => Box::new(PrefixMatcher),
+            MatcherKind::Acronym => Box::new(AcronymMatcher),
+            MatcherKind::Regex => Box::new(RegexMatcher::default()),
+        }
+    }
+}
+
+/// Matches `pattern` against the text of each item, returning the matching
+/// items with the char indices of their matches, best match first and in
+/// the given order among equal ones. Every item matches an empty pattern.
+pub fn rank_by_match<'a, T>(
+    items: impl IntoIterator<Item = &'a T>,
+    text: impl Fn(&T) -> &str,
+    pattern: &str,
+    matcher: &dyn Matcher,
+    case: CaseMode,
+) -> Vec<(&'a T, Vec<usize>)> {
+    let pattern = pattern.trim();
+    let case_sensitive = case.is_sensitive(pattern);
+    let mut matches: Vec<(i64, &'a T, Vec<usize>)> = items
+        .into_iter()
+        .filter_map(|item| {
+            let (score, indices) = if pattern.is_empty() {
+                (0, Vec::new())
+            } else {
+                matcher.match_indices(text(item), pattern, case_sensitive)?
+            };
+            Some((score, item, indices))
+        })
+        .collect();
+    // Stable, so the given order breaks ties
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, item, indices)| (item, indices)).collect()
+}
+
+pub fn chars_equal(a: char, b: char, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        a == b
+    } else {
+        a == b || a.to_lowercase().eq(b.to_lowercase())
+    }
+}
+
+/// Finds `needle` in `haystack` at or after `from`, returning the char
+/// index of the first occurrence.
+pub fn find_chars(haystack: &[char], needle: &[char], from: usize, case_sensitive: bool) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (from..=haystack.len() - needle.len()).find(|&start| {
+        haystack[start..start + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| chars_equal(*a, *b, case_sensitive))
+    })
+}
+
+fn is_word_start(text: &[char], index: usize) -> bool {
+    match index.checked_sub(1).map(|prev| text[prev]) {
+        None => true,
+        Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && text[index].is_uppercase()),
+    }
+}
+
+/// Scores a contiguous match of `len` chars at `start` on roughly the same
+/// scale as skim's fuzzy scores, favouring matches at word starts.
+pub fn contiguous_score(text: &[char], start: usize, len: usize) -> i64 {
+    let boundary_bonus = if is_word_start(text, start) { 16 } else { 0 };
+    len as i64 * 24 + boundary_bonus - (start as i64).min(10)
+}
+
+struct SkimMatcher {
+    respect_case: SkimMatcherV2,
+    ignore_case: SkimMatcherV2,
+}
+
+impl Matcher for SkimMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        if case_sensitive {
+            self.respect_case.fuzzy_indices(text, pattern)
+        } else {
+            self.ignore_case.fuzzy_indices(text, pattern)
+        }
+    }
+}
+
+struct ClangdMatcher {
+    respect_case: ClangdFuzzyMatcher,
+    ignore_case: ClangdFuzzyMatcher,
+}
+
+impl Matcher for ClangdMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        if case_sensitive {
+            self.respect_case.fuzzy_indices(text, pattern)
+        } else {
+            self.ignore_case.fuzzy_indices(text, pattern)
+        }
+    }
+}
+
+struct SubstringMatcher;
+
+impl Matcher for SubstringMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        let haystack: Vec<char> = text.chars().collect();
+        let needle: Vec<char> = pattern.chars().collect();
+        let start = find_chars(&haystack, &needle, 0, case_sensitive)?;
+        Some((contiguous_score(&haystack, start, needle.len()), (start..start + needle.len()).collect()))
+    }
+}
+
+struct PrefixMatcher;
+
+impl Matcher for PrefixMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        let haystack: Vec<char> = text.chars().collect();
+        let needle: Vec<char> = pattern.chars().collect();
+        if needle.len() > haystack.len()
+            || !haystack.iter().zip(&needle).all(|(a, b)| chars_equal(*a, *b, case_sensitive))
+        {
+            return None;
+        }
+        Some((contiguous_score(&haystack, 0, needle.len()), (0..needle.len()).collect()))
+    }
+}
+
+struct AcronymMatcher;
+
+impl Matcher for AcronymMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        let haystack: Vec<char> = text.chars().collect();
+        let word_starts: Vec<usize> = (0..haystack.len())
+            .filter(|&i| haystack[i].is_alphanumeric() && is_word_start(&haystack, i))
+            .collect();
+
+        // Match each pattern char to the next word initial, skipping words
+        // that don't match
+        let mut indices = Vec::new();
+        let mut words = word_starts.iter();
+        for c in pattern.chars() {
+            let index = words.find(|&&i| chars_equal(haystack[i], c, case_sensitive))?;
+            indices.push(*index);
+        }
+
+        if indices.is_empty() {
+            return None;
+        }
+
+        // Prefer acronyms starting at the first word that skip few words
+        let skipped = word_starts.iter().filter(|i| **i <= indices[indices.len() - 1]).count() - indices.len();
+        let first_word_bonus = if indices[0] == word_starts[0] { 16 } else { 0 };
+        let score = indices.len() as i64 * 24 + first_word_bonus - skipped as i64 * 8;
+        Some((score.max(1), indices))
+    }
+}
+
+/// Compiles regexes lazily and keeps the last one, since the same pattern is
+/// matched against every field of every entry, possibly from several
+/// threads at once.
+#[derive(Default)]
+struct RegexMatcher {
+    cache: RwLock<Option<(String, bool, Option<Regex>)>>,
+}
+
+impl RegexMatcher {
+    fn regex(&self, pattern: &str, case_sensitive: bool) -> Option<Regex> {
+        let is_cached = |cache: &Option<(String, bool, Option<Regex>)>| {
+            cache
+                .as_ref()
+                .is_some_and(|(cached, cached_case, _)| cached == pattern && *cached_case == case_sensitive)
+        };
+
+        {
+            let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+            if is_cached(&cache) {
+                return cache.as_ref().and_then(|(_, _, regex)| regex.clone());
+            }
+        }
+
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        if !is_cached(&cache) {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .ok();
+            *cache = Some((pattern.to_string(), case_sensitive, regex));
+        }
+        cache.as_ref().and_then(|(_, _, regex)| regex.clone())
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn match_indices(&self, text: &str, pattern: &str, case_sensitive: bool) -> Option<(i64, Vec<usize>)> {
+        let regex = self.regex(pattern, case_sensitive)?;
+        let found = regex.find(text)?;
+        if found.is_empty() {
+            return None;
+        }
+
+        let start = text[..found.start()].chars().count();
+        let len = found.as_str().chars().count();
+        let haystack: Vec<char> = text.chars().collect();
+        Some((contiguous_score(&haystack, start, len), (start..start + len).collect()))
+    }
+
+    fn narrows(&self) -> bool {
+        // Appending can widen a regex, e.g. `ab` to `ab?` or `a` to `a|b`
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(kind: MatcherKind, text: &str, pattern: &str) -> Option<Vec<usize>> {
+        let case_sensitive = CaseMode::Smart.is_sensitive(pattern);
+        kind.build().match_indices(text, pattern, case_sensitive).map(|(_, indices)| indices)
+    }
+
+    #[test]
+    fn substring_and_prefix() {
+        assert_eq!(indices(MatcherKind::Substring, "Firefox", "fox"), Some(vec![4, 5, 6]));
+        assert_eq!(indices(MatcherKind::Substring, "Firefox", "ffx"), None);
+        assert_eq!(indices(MatcherKind::Prefix, "Firefox", "fire"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(indices(MatcherKind::Prefix, "Firefox", "fox"), None);
+    }
+
+    #[test]
+    fn acronym_matches_word_initials() {
+        assert_eq!(indices(MatcherKind::Acronym, "Visual Studio Code", "vsc"), Some(vec![0, 7, 14]));
+        assert_eq!(indices(MatcherKind::Acronym, "Visual Studio Code", "vc"), Some(vec![0, 14]));
+        assert_eq!(indices(MatcherKind::Acronym, "LibreOffice Calc", "loc"), Some(vec![0, 5, 12]));
+        assert_eq!(indices(MatcherKind::Acronym, "Visual Studio Code", "vis"), None);
+    }
+
+    #[test]
+    fn acronym_prefers_fewer_skipped_words() {
+        let matcher = MatcherKind::Acronym.build();
+        let (direct, _) = matcher.match_indices("Visual Studio Code", "vs", false).unwrap();
+        let (skipping, _) = matcher.match_indices("Visual Studio Code", "vc", false).unwrap();
+        assert!(direct > skipping);
+    }
+
+    #[test]
+    fn regex_reports_char_indices() {
+        assert_eq!(indices(MatcherKind::Regex, "Résumé Editor", "ed.t"), Some(vec![7, 8, 9, 10]));
+        assert_eq!(indices(MatcherKind::Regex, "Firefox", "^fox"), None);
+        assert_eq!(indices(MatcherKind::Regex, "Firefox", "("), None);
+    }
+
+    #[test]
+    fn next_cycles_through_all_kinds() {
+        let mut kind = MatcherKind::Skim;
+        for _ in 0..MatcherKind::ALL.len() {
+            kind = kind.next();
+        }
+        assert_eq!(kind, MatcherKind::Skim);
+    }
+}
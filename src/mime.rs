@@ -0,0 +1,472 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Bytes read from the start of a file for magic sniffing
+const SNIFF_LENGTH: usize = 16 * 1024;
+
+pub const TEXT_PLAIN: &str = "text/plain";
+pub const OCTET_STREAM: &str = "application/octet-stream";
+pub const DIRECTORY: &str = "inode/directory";
+
+/// A pattern from a `globs2` file.
+#[derive(Debug, Clone)]
+struct Glob {
+    weight: u32,
+    pattern: String,
+    mime: String,
+    case_sensitive: bool,
+}
+
+impl Glob {
+    fn matches(&self, file_name: &str, lowercase_name: &str) -> bool {
+        let name = if self.case_sensitive { file_name } else { lowercase_name };
+        wildcard_match(self.pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// One line of a magic section: the bytes `value` (under `mask`) at
+/// `offset`, or anywhere up to `range` bytes after it.
+#[derive(Debug, Clone)]
+struct MagicRule {
+    indent: usize,
+    offset: usize,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    range: usize,
+}
+
+impl MagicRule {
+    fn test(&self, data: &[u8]) -> bool {
+        (self.offset..self.offset + self.range).any(|start| {
+            let Some(window) = data.get(start..start + self.value.len()) else {
+                return false;
+            };
+            match &self.mask {
+                Some(mask) => window
+                    .iter()
+                    .zip(&self.value)
+                    .zip(mask)
+                    .all(|((byte, value), mask)| byte & mask == value & mask),
+                None => window == self.value.as_slice(),
+            }
+        })
+    }
+}
+
+/// The rules of a `[priority:type]` section of a `magic` file.
+#[derive(Debug, Clone)]
+struct Magic {
+    priority: u32,
+    mime: String,
+    rules: Vec<MagicRule>,
+}
+
+impl Magic {
+    fn matches(&self, data: &[u8]) -> bool {
+        rules_match(&self.rules, 0, data)
+    }
+}
+
+/// Whether any rule at `indent` matches together with at least one of its
+/// nested rules, if it has any.
+fn rules_match(rules: &[MagicRule], indent: usize, data: &[u8]) -> bool {
+    let mut start = 0;
+    while start < rules.len() {
+        let end = rules[start + 1..]
+            .iter()
+            .position(|rule| rule.indent <= indent)
+            .map_or(rules.len(), |position| start + 1 + position);
+        let children = &rules[start + 1..end];
+        if rules[start].test(data) && (children.is_empty() || rules_match(children, indent + 1, data)) {
+            return true;
+        }
+        start = end;
+    }
+    false
+}
+
+/// The shared MIME-info database of freedesktop.org, used to tell a file's
+/// type by its name and, where that is ambiguous, its content.
+#[derive(Debug, Default)]
+pub struct MimeDatabase {
+    /// Highest weight first
+    globs: Vec<Glob>,
+    /// Highest priority first
+    magic: Vec<Magic>,
+    aliases: HashMap<String, String>,
+    parents: HashMap<String, Vec<String>>,
+}
+
+impl MimeDatabase {
+    /// Loads the database from the `mime` directories of the XDG data
+    /// directories. Missing or unreadable files are skipped.
+    pub fn load() -> Self {
+        Self::from_dirs(&data_dirs().into_iter().map(|dir| dir.join("mime")).collect::<Vec<_>>())
+    }
+
+    /// Loads the database from `dirs`, most important first.
+    pub fn from_dirs(dirs: &[PathBuf]) -> Self {
+        let mut database = Self::default();
+        for dir in dirs {
+            if let Ok(content) = fs::read_to_string(dir.join("globs2")) {
+                database.parse_globs(&content);
+            }
+            if let Ok(content) = fs::read(dir.join("magic")) {
+                database.parse_magic(&content);
+            }
+            if let Ok(content) = fs::read_to_string(dir.join("aliases")) {
+                for (alias, mime) in content.lines().filter_map(|line| line.split_once(' ')) {
+                    database.aliases.entry(alias.to_string()).or_insert_with(|| mime.to_string());
+                }
+            }
+            if let Ok(content) = fs::read_to_string(dir.join("subclasses")) {
+                for (child, parent) in content.lines().filter_map(|line| line.split_once(' ')) {
+                    database.parents.entry(child.to_string()).or_default().push(parent.to_string());
+                }
+            }
+        }
+
+        database.sort();
+        database
+    }
+
+    fn sort(&mut self) {
+        // Stable, so earlier directories win ties
+        self.globs.sort_by_key(|glob| Reverse(glob.weight));
+        self.magic.sort_by_key(|magic| Reverse(magic.priority));
+    }
+
+    fn parse_globs(&mut self, content: &str) {
+        for line in content.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.split(':');
+            let (Some(weight), Some(mime), Some(pattern)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let Ok(weight) = weight.parse() else {
+                continue;
+            };
+            let case_sensitive = fields.next().is_some_and(|flags| flags.split(',').any(|flag| flag == "cs"));
+            self.globs.push(Glob {
+                weight,
+                pattern: if case_sensitive { pattern.to_string() } else { pattern.to_lowercase() },
+                mime: mime.to_string(),
+                case_sensitive,
+            });
+        }
+    }
+
+    /// Parses the binary `magic` file format of shared-mime-info.
+    fn parse_magic(&mut self, content: &[u8]) {
+        let Some(mut rest) = content.strip_prefix(b"MIME-Magic\0\n") else {
+            return;
+        };
+
+        while let Some(section) = rest.strip_prefix(b"[") {
+            let Some(end) = section.iter().position(|&byte| byte == b'\n') else {
+                return;
+            };
+            let header = String::from_utf8_lossy(&section[..end]);
+            rest = &section[end + 1..];
+
+            let mut magic = header
+                .strip_suffix(']')
+                .and_then(|header| header.split_once(':'))
+                .and_then(|(priority, mime)| {
+                    Some(Magic { priority: priority.parse().ok()?, mime: mime.to_string(), rules: Vec::new() })
+                });
+
+            while !rest.is_empty() && rest[0] != b'[' {
+                let Some((rule, remaining)) = parse_magic_rule(rest) else {
+                    // Unknown syntax: skip to the next line, as the spec asks
+                    let Some(end) = rest.iter().position(|&byte| byte == b'\n') else {
+                        return;
+                    };
+                    rest = &rest[end + 1..];
+                    continue;
+                };
+                rest = remaining;
+                if let Some(magic) = &mut magic {
+                    magic.rules.push(rule);
+                }
+            }
+
+            if let Some(magic) = magic {
+                self.magic.push(magic);
+            }
+        }
+    }
+
+    /// Returns the canonical name of `mime`, resolving aliases.
+    pub fn unalias<'a>(&'a self, mime: &'a str) -> &'a str {
+        self.aliases.get(mime).map_or(mime, String::as_str)
+    }
+
+    /// Guesses the type of the file at `path`: by its name if only one type
+    /// fits best, otherwise by its content.
+    pub fn guess(&self, path: &Path) -> String {
+        if path.is_dir() {
+            return DIRECTORY.to_string();
+        }
+
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let by_name = self.guess_by_name(&file_name);
+        if let [mime] = by_name.as_slice() {
+            return mime.clone();
+        }
+
+        let data = read_start(path);
+        if let Some(mime) = self.sniff(&data, &by_name) {
+            return mime;
+        }
+        if let Some(mime) = by_name.first() {
+            return mime.clone();
+        }
+        if looks_like_text(&data) {
+            TEXT_PLAIN.to_string()
+        } else {
+            OCTET_STREAM.to_string()
+        }
+    }
+
+    /// Returns the types whose best matching pattern fits `file_name`,
+    /// preferring higher weights and then longer patterns.
+    fn guess_by_name(&self, file_name: &str) -> Vec<String> {
+        let lowercase_name = file_name.to_lowercase();
+        let mut best: Option<(u32, usize)> = None;
+        let mut types: Vec<String> = Vec::new();
+        for glob in self.globs.iter().filter(|glob| glob.matches(file_name, &lowercase_name)) {
+            let rank = (glob.weight, glob.pattern.len());
+            if best.is_some_and(|best| rank < best) {
+                continue;
+            }
+            if best != Some(rank) {
+                best = Some(rank);
+                types.clear();
+            }
+            let mime = self.unalias(&glob.mime).to_string();
+            if !types.contains(&mime) {
+                types.push(mime);
+            }
+        }
+        types
+    }
+
+    /// Returns the type of the best matching magic, restricted to
+    /// `candidates` and their subtypes unless that is empty.
+    fn sniff(&self, data: &[u8], candidates: &[String]) -> Option<String> {
+        self.magic
+            .iter()
+            .filter(|magic| {
+                candidates.is_empty()
+                    || candidates
+                        .iter()
+                        .any(|candidate| self.ancestors(&magic.mime).contains(candidate))
+            })
+            .find(|magic| magic.matches(data))
+            .map(|magic| self.unalias(&magic.mime).to_string())
+    }
+
+    /// Returns `mime` followed by the types it is a subclass of, nearest
+    /// first. Every `text/*` type is also `text/plain`.
+    pub fn ancestors(&self, mime: &str) -> Vec<String> {
+        let mut ancestors = vec![self.unalias(mime).to_string()];
+        let mut index = 0;
+        while index < ancestors.len() {
+            let mut parents = self.parents.get(&ancestors[index]).cloned().unwrap_or_default();
+            if ancestors[index].starts_with("text/") && ancestors[index] != TEXT_PLAIN {
+                parents.push(TEXT_PLAIN.to_string());
+            }
+            for parent in parents {
+                let parent = self.unalias(&parent).to_string();
+                if !ancestors.contains(&parent) {
+                    ancestors.push(parent);
+                }
+            }
+            index += 1;
+        }
+        ancestors
+    }
+}
+
+/// Parses one magic rule line:
+/// `[indent]>offset=<u16 length><value>[&<mask>][~word-size][+range]\n`.
+fn parse_magic_rule(line: &[u8]) -> Option<(MagicRule, &[u8])> {
+    fn number(input: &[u8]) -> Option<(usize, &[u8])> {
+        let digits = input.iter().take_while(|byte| byte.is_ascii_digit()).count();
+        let value = std::str::from_utf8(&input[..digits]).ok()?.parse().ok()?;
+        Some((value, &input[digits..]))
+    }
+
+    let (indent, rest) = match line.first()? {
+        b'>' => (0, line),
+        _ => number(line)?,
+    };
+    let (offset, rest) = number(rest.strip_prefix(b">")?)?;
+    let rest = rest.strip_prefix(b"=")?;
+    let length = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+    let mut value = rest.get(2..2 + length)?.to_vec();
+    let mut rest = &rest[2 + length..];
+
+    let mut mask = None;
+    if let Some(masked) = rest.strip_prefix(b"&") {
+        mask = Some(masked.get(..length)?.to_vec());
+        rest = &masked[length..];
+    }
+    let mut word_size = 1;
+    if let Some(sized) = rest.strip_prefix(b"~") {
+        (word_size, rest) = number(sized)?;
+    }
+    let mut range = 1;
+    if let Some(ranged) = rest.strip_prefix(b"+") {
+        (range, rest) = number(ranged)?;
+    }
+    let rest = rest.strip_prefix(b"\n")?;
+
+    // Values of multi-byte words are stored big-endian
+    if cfg!(target_endian = "little") && word_size > 1 && length.is_multiple_of(word_size) {
+        value.chunks_mut(word_size).for_each(<[u8]>::reverse);
+        if let Some(mask) = &mut mask {
+            mask.chunks_mut(word_size).for_each(<[u8]>::reverse);
+        }
+    }
+
+    Some((MagicRule { indent, offset, value, mask, range: range.max(1) }, rest))
+}
+
+/// Matches shell wildcards: `*`, `?` and `[...]` character classes.
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'*') => (0..=name.len()).any(|skip| wildcard_match(&pattern[1..], &name[skip..])),
+        Some(b'?') => !name.is_empty() && wildcard_match(&pattern[1..], &name[1..]),
+        Some(b'[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&byte| byte == b']').map(|end| end + 2) else {
+                return name.first() == Some(&b'[') && wildcard_match(&pattern[1..], &name[1..]);
+            };
+            let Some(&byte) = name.first() else {
+                return false;
+            };
+            let (negated, class) = match pattern[1] {
+                b'!' | b'^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let mut found = false;
+            let mut index = 0;
+            while index < class.len() {
+                if class.get(index + 1) == Some(&b'-') && index + 2 < class.len() {
+                    found |= (class[index]..=class[index + 2]).contains(&byte);
+                    index += 3;
+                } else {
+                    found |= class[index] == byte;
+                    index += 1;
+                }
+            }
+            found != negated && wildcard_match(&pattern[end + 1..], &name[1..])
+        }
+        Some(&literal) => name.first() == Some(&literal) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+fn read_start(path: &Path) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(SNIFF_LENGTH as u64).read_to_end(&mut data);
+    }
+    data
+}
+
+/// Whether `data` looks like text rather than binary data, as
+/// shared-mime-info decides for files without a better match.
+fn looks_like_text(data: &[u8]) -> bool {
+    !data.iter().take(128).any(|&byte| byte < 0x20 && !b"\t\n\r\x0c".contains(&byte))
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, with the defaults of the
+/// base directory spec.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::data_dir().into_iter().collect();
+    let system = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(system.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from));
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> MimeDatabase {
+        let mut database = MimeDatabase::default();
+        database.parse_globs(
+            "# comment\n\
+             50:application/pdf:*.pdf\n\
+             50:text/x-csrc:*.c\n\
+             50:text/x-c++src:*.C:cs\n\
+             50:application/gzip:*.gz\n\
+             50:application/x-compressed-tar:*.tar.gz\n\
+             50:text/x-makefile:makefile\n\
+             50:text/x-makefile:[Mm]akefile\n",
+        );
+
+        let mut magic = b"MIME-Magic\0\n[50:application/pdf]\n>0=\0\x05%PDF-\n".to_vec();
+        magic.extend_from_slice(b"[40:image/png]\n>0=\0\x04\x89PNG\n");
+        magic.extend_from_slice(b"[30:text/x-script]\n>0=\0\x02#!\n1>2=\0\x04/bin+8\n");
+        database.parse_magic(&magic);
+        database.sort();
+        database
+            .parents
+            .insert("application/x-compressed-tar".to_string(), vec!["application/gzip".to_string()]);
+        database
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard_match(b"*.tar.gz", b"a.tar.gz"));
+        assert!(wildcard_match(b"[mm]akefile", b"makefile"));
+        assert!(wildcard_match(b"core.[0-9]", b"core.7"));
+        assert!(!wildcard_match(b"core.[!0-9]", b"core.7"));
+        assert!(!wildcard_match(b"*.pdf", b"pdf"));
+    }
+
+    #[test]
+    fn guesses_by_name() {
+        let database = database();
+        assert_eq!(database.guess_by_name("Report.PDF"), ["application/pdf"]);
+        assert_eq!(database.guess_by_name("main.c"), ["text/x-csrc"]);
+        assert_eq!(database.guess_by_name("main.C"), ["text/x-csrc", "text/x-c++src"]);
+        // The longest pattern wins
+        assert_eq!(database.guess_by_name("src.tar.gz"), ["application/x-compressed-tar"]);
+        assert!(database.guess_by_name("README").is_empty());
+    }
+
+    #[test]
+    fn sniffs_content() {
+        let database = database();
+        assert_eq!(database.sniff(b"%PDF-1.7", &[]).as_deref(), Some("application/pdf"));
+        assert_eq!(database.sniff(b"\x89PNG\r\n", &[]).as_deref(), Some("image/png"));
+        // Nested rules must match too, anywhere within the range
+        assert_eq!(database.sniff(b"#!/usr/bin/sh", &[]).as_deref(), Some("text/x-script"));
+        assert_eq!(database.sniff(b"#!sh", &[]), None);
+        // Only types the name allows
+        assert_eq!(database.sniff(b"%PDF-1.7", &["image/png".to_string()]), None);
+
+        assert!(looks_like_text(b"hello\tworld\n"));
+        assert!(!looks_like_text(b"\x7fELF\x02\x01\x01\0"));
+    }
+
+    #[test]
+    fn lists_ancestors() {
+        let database = database();
+        assert_eq!(
+            database.ancestors("application/x-compressed-tar"),
+            ["application/x-compressed-tar", "application/gzip"]
+        );
+        assert_eq!(database.ancestors("text/x-csrc"), ["text/x-csrc", "text/plain"]);
+    }
+}
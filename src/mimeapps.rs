@@ -0,0 +1,275 @@
+use crate::desktop_entry::AppEntry;
+use crate::mime::{data_dirs, MimeDatabase};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The associations of one `mimeapps.list` file: desktop file IDs by type.
+#[derive(Debug, Default)]
+struct Associations {
+    defaults: HashMap<String, Vec<String>>,
+    added: HashMap<String, Vec<String>>,
+    removed: HashMap<String, Vec<String>>,
+}
+
+impl Associations {
+    fn parse(content: &str) -> Self {
+        let mut associations = Self::default();
+        let mut group = None;
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('[') && line.ends_with(']') {
+                group = match line {
+                    // `defaults.list`, the predecessor of `mimeapps.list`,
+                    // uses the same group name
+                    "[Default Applications]" => Some(&mut associations.defaults),
+                    "[Added Associations]" => Some(&mut associations.added),
+                    "[Removed Associations]" => Some(&mut associations.removed),
+                    _ => None,
+                };
+                continue;
+            }
+
+            let (Some(map), Some((mime, ids))) = (group.as_deref_mut(), line.split_once('=')) else {
+                continue;
+            };
+            map.entry(mime.trim().to_string()).or_default().extend(
+                ids.split(';')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        associations
+    }
+}
+
+/// The user's and the system's choices of applications for file types,
+/// from the `mimeapps.list` files of the XDG MIME applications spec.
+#[derive(Debug, Default)]
+pub struct MimeApps {
+    /// Most important first
+    files: Vec<Associations>,
+}
+
+impl MimeApps {
+    /// Loads every `mimeapps.list` in the spec's lookup order, including
+    /// the desktop-specific ones for `$XDG_CURRENT_DESKTOP`.
+    pub fn load() -> Self {
+        let desktops: Vec<String> = env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .split(':')
+            .filter(|desktop| !desktop.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+
+        let mut dirs: Vec<PathBuf> = dirs::config_dir().into_iter().collect();
+        let config_dirs = env::var("XDG_CONFIG_DIRS")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| "/etc/xdg".to_string());
+        dirs.extend(config_dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from));
+        let application_dirs: Vec<PathBuf> = data_dirs().into_iter().map(|dir| dir.join("applications")).collect();
+        dirs.extend(application_dirs.iter().cloned());
+
+        let mut paths = Vec::new();
+        for dir in &dirs {
+            for desktop in &desktops {
+                paths.push(dir.join(format!("{}-mimeapps.list", desktop)));
+            }
+            paths.push(dir.join("mimeapps.list"));
+        }
+        paths.extend(application_dirs.iter().map(|dir| dir.join("defaults.list")));
+
+        Self {
+            files: paths
+                .iter()
+                .filter_map(|path| fs::read_to_string(path).ok())
+                .map(|content| Associations::parse(&content))
+                .collect(),
+        }
+    }
+
+    /// Returns the application to open a file of the given types with;
+    /// `mime_types` lists the file's type first, then broader ones it is
+    /// a subclass of. Per type, the defaults come first, then added
+    /// associations, then any application declaring support for it, leaving
+    /// out removed associations. `mime` resolves the aliases applications
+    /// may declare support for.
+    pub fn default_handler<'a>(
+        &self,
+        mime_types: &[String],
+        apps: &'a [AppEntry],
+        mime: &MimeDatabase,
+    ) -> Option<&'a AppEntry> {
+        let find = |id: &str| apps.iter().find(|app| app.id == id);
+        for mime_type in mime_types {
+            let defaults = self.files.iter().filter_map(|file| file.defaults.get(mime_type)).flatten();
+            if let Some(app) = defaults.filter_map(|id| find(id)).next() {
+                return Some(app);
+            }
+
+            let (added, removed) = self.added_and_removed(mime_type);
+            if let Some(app) = added.into_iter().filter_map(|id| find(id)).next() {
+                return Some(app);
+            }
+
+            let supporting = apps
+                .iter()
+                .filter(|app| supports(app, mime_type, mime) && !removed.contains(&&app.id))
+                .min_by(|a, b| a.id.cmp(&b.id));
+            if supporting.is_some() {
+                return supporting;
+            }
+        }
+        None
+    }
+
+    /// Returns every application associated with the given types, as for
+    /// [`default_handler`](Self::default_handler), which comes first. The
+    /// rest follow in the order of `mime_types`, then of the lists they
+    /// come from, then by ID.
+    pub fn handlers<'a>(&self, mime_types: &[String], apps: &'a [AppEntry], mime: &MimeDatabase) -> Vec<&'a AppEntry> {
+        let find = |id: &str| apps.iter().find(|app| app.id == id);
+        let mut handlers: Vec<&AppEntry> = self.default_handler(mime_types, apps, mime).into_iter().collect();
+        for mime_type in mime_types {
+            let defaults = self.files.iter().filter_map(|file| file.defaults.get(mime_type)).flatten();
+            let (added, removed) = self.added_and_removed(mime_type);
+            let mut supporting: Vec<&AppEntry> = apps
+                .iter()
+                .filter(|app| supports(app, mime_type, mime) && !removed.contains(&&app.id))
+                .collect();
+            supporting.sort_by(|a, b| a.id.cmp(&b.id));
+
+            for app in defaults.chain(added).filter_map(|id| find(id)).chain(supporting) {
+                if !handlers.iter().any(|handler| handler.id == app.id) {
+                    handlers.push(app);
+                }
+            }
+        }
+        handlers
+    }
+
+    /// Returns the applications added for `mime`, most important first,
+    /// and the ones removed for it. A removal hides what the same or less
+    /// important files add, while added associations of more important
+    /// files stay; every removal hides applications merely declaring
+    /// support, which matter least.
+    fn added_and_removed(&self, mime: &str) -> (Vec<&String>, Vec<&String>) {
+        let mut added = Vec::new();
+        let mut removed: Vec<&String> = Vec::new();
+        for file in &self.files {
+            removed.extend(file.removed.get(mime).into_iter().flatten());
+            added.extend(file.added.get(mime).into_iter().flatten().filter(|id| !removed.contains(id)));
+        }
+        (added, removed)
+    }
+}
+
+/// Whether `app` declares support for `mime_type`, possibly by an alias.
+fn supports(app: &AppEntry, mime_type: &str, mime: &MimeDatabase) -> bool {
+    app.mime_types.iter().any(|declared| mime.unalias(declared) == mime_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(id: &str, mime_types: &[&str]) -> AppEntry {
+        AppEntry {
+            exec: "true %f".to_string(),
+            mime_types: mime_types.iter().map(|mime| mime.to_string()).collect(),
+            ..AppEntry::for_test(id, id)
+        }
+    }
+
+    fn types(types: &[&str]) -> Vec<String> {
+        types.iter().map(|mime| mime.to_string()).collect()
+    }
+
+    #[test]
+    fn resolves_defaults_before_supporting_applications() {
+        let apps = [
+            app("evince.desktop", &["application/pdf"]),
+            app("okular.desktop", &["application/pdf"]),
+            app("gedit.desktop", &["text/plain"]),
+            app("vim.desktop", &["text/plain"]),
+        ];
+        let handler = |mime_apps: &MimeApps, mime_types: &[&str]| {
+            mime_apps.default_handler(&types(mime_types), &apps, &MimeDatabase::default()).map(|app| app.id.as_str())
+        };
+
+        let user = Associations::parse(
+            "[Default Applications]\n\
+             application/pdf=missing.desktop;okular.desktop;\n\
+             [Removed Associations]\n\
+             text/plain=gedit.desktop\n",
+        );
+        let mime_apps = MimeApps { files: vec![user] };
+        assert_eq!(handler(&mime_apps, &["application/pdf"]), Some("okular.desktop"));
+        assert_eq!(handler(&mime_apps, &["text/x-csrc", "text/plain"]), Some("vim.desktop"));
+        assert_eq!(handler(&mime_apps, &["image/png"]), None);
+
+        assert_eq!(handler(&MimeApps::default(), &["application/pdf"]), Some("evince.desktop"));
+    }
+
+    #[test]
+    fn lists_every_associated_application() {
+        let apps = [
+            app("gedit.desktop", &["text/plain"]),
+            app("kate.desktop", &["text/x-csrc", "text/plain"]),
+            app("vim.desktop", &["text/plain"]),
+            app("nano.desktop", &[]),
+        ];
+        let user = Associations::parse(
+            "[Default Applications]\n\
+             text/plain=vim.desktop\n\
+             [Added Associations]\n\
+             text/plain=nano.desktop\n\
+             [Removed Associations]\n\
+             text/plain=gedit.desktop\n",
+        );
+        let mime_apps = MimeApps { files: vec![user] };
+        let ids: Vec<&str> = mime_apps
+            .handlers(&types(&["text/x-csrc", "text/plain"]), &apps, &MimeDatabase::default())
+            .into_iter()
+            .map(|app| app.id.as_str())
+            .collect();
+        assert_eq!(ids, ["kate.desktop", "vim.desktop", "nano.desktop"]);
+    }
+
+    #[test]
+    fn removals_hide_only_less_important_additions() {
+        let apps = [app("gedit.desktop", &["text/plain"]), app("nano.desktop", &[])];
+        let user = Associations::parse(
+            "[Added Associations]\n\
+             text/plain=nano.desktop\n",
+        );
+        let system = Associations::parse(
+            "[Added Associations]\n\
+             text/plain=gedit.desktop\n\
+             [Removed Associations]\n\
+             text/plain=nano.desktop;gedit.desktop\n",
+        );
+        let mime_apps = MimeApps { files: vec![user, system] };
+        let ids: Vec<&str> = mime_apps
+            .handlers(&types(&["text/plain"]), &apps, &MimeDatabase::default())
+            .into_iter()
+            .map(|app| app.id.as_str())
+            .collect();
+        assert_eq!(ids, ["nano.desktop"]);
+    }
+
+    #[test]
+    fn resolves_aliases_applications_support() {
+        let dir = std::env::temp_dir().join(format!("exek-mimeapps-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("aliases"), "application/x-pdf application/pdf\n").unwrap();
+        let mime = MimeDatabase::from_dirs(std::slice::from_ref(&dir));
+        let _ = fs::remove_dir_all(&dir);
+
+        let apps = [app("evince.desktop", &["application/x-pdf"])];
+        let handler = MimeApps::default().default_handler(&types(&["application/pdf"]), &apps, &mime);
+        assert_eq!(handler.map(|app| app.id.as_str()), Some("evince.desktop"));
+    }
+}
@@ -0,0 +1,111 @@
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+/// Letters that don't decompose but are commonly typed as their ASCII
+/// spelling.
+fn expand_ligature(c: char) -> Option<&'static str> {
+    match c {
+        'ß' => Some("ss"),
+        'ẞ' => Some("SS"),
+        'æ' => Some("ae"),
+        'Æ' => Some("AE"),
+        'œ' => Some("oe"),
+        'Œ' => Some("OE"),
+        'ø' => Some("o"),
+        'Ø' => Some("O"),
+        'đ' => Some("d"),
+        'Đ' => Some("D"),
+        'ł' => Some("l"),
+        'Ł' => Some("L"),
+        'þ' => Some("th"),
+        'Þ' => Some("TH"),
+        'ı' => Some("i"),
+        _ => None,
+    }
+}
+
+/// Text prepared for matching, with a map from each of its chars back to
+/// the char of the original text it came from, so match indices can be
+/// reported against the original.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    pub text: String,
+    origin: Vec<usize>,
+}
+
+impl Normalized {
+    /// Folds case if `fold_case` is set, and if `strip` is set decomposes
+    /// compatibility characters (`ﬁ` to `fi`), strips combining marks (`é`
+    /// to `e`) and expands ligatures (`ß` to `ss`).
+    pub fn new(text: &str, fold_case: bool, strip: bool) -> Self {
+        let mut normalized = String::with_capacity(text.len());
+        let mut origin = Vec::with_capacity(text.len());
+
+        for (index, c) in text.chars().enumerate() {
+            let mut push = |c: char| {
+                let mut push_folded = |c: char| {
+                    if !(strip && is_combining_mark(c)) {
+                        normalized.push(c);
+                        origin.push(index);
+                    }
+                };
+                if fold_case {
+                    c.to_lowercase().for_each(&mut push_folded);
+                } else {
+                    push_folded(c);
+                }
+            };
+
+            if !strip {
+                push(c);
+            } else if let Some(expansion) = expand_ligature(c) {
+                expansion.chars().for_each(&mut push);
+            } else {
+                decompose_compatible(c, &mut push);
+            }
+        }
+
+        Self {
+            text: normalized,
+            origin,
+        }
+    }
+
+    /// Maps char indices into the normalized text back to char indices into
+    /// the original text.
+    pub fn original_indices(&self, indices: &[usize]) -> Vec<usize> {
+        let mut original: Vec<usize> = indices
+            .iter()
+            .filter_map(|&i| self.origin.get(i).copied())
+            .collect();
+        original.dedup();
+        original
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_diacritics_and_folds_case() {
+        assert_eq!(Normalized::new("Résumé Editor", true, true).text, "resume editor");
+        assert_eq!(Normalized::new("Résumé", false, true).text, "Resume");
+        assert_eq!(Normalized::new("Résumé", true, false).text, "résumé");
+    }
+
+    #[test]
+    fn expands_ligatures() {
+        assert_eq!(Normalized::new("Straße", true, true).text, "strasse");
+        assert_eq!(Normalized::new("ﬁle Œuvre", true, true).text, "file oeuvre");
+    }
+
+    #[test]
+    fn maps_indices_to_original() {
+        let normalized = Normalized::new("Straße", true, true);
+        // "strasse": the two s of ß map back to the same char
+        assert_eq!(normalized.original_indices(&[3, 4, 5, 6]), vec![3, 4, 5]);
+
+        let normalized = Normalized::new("Ça va", true, true);
+        assert_eq!(normalized.original_indices(&[0, 1]), vec![0, 1]);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::database::Database;
+use crate::desktop_entry::AppEntry;
+use crate::matcher::{rank_by_match, CaseMode, Matcher};
+use crate::mime::MimeDatabase;
+use crate::mimeapps::MimeApps;
+use std::path::PathBuf;
+
+/// An application matching the query in the "open with" chooser.
+#[derive(Debug, Clone)]
+pub struct HandlerMatch {
+    pub app: AppEntry,
+    /// Character indices in the application's name
+    pub match_indices: Vec<usize>,
+}
+
+/// The applications offered for opening a file, listed instead of search
+/// results until one is chosen.
+#[derive(Debug, Clone)]
+pub struct OpenWith {
+    pub file: PathBuf,
+    /// The file's type, which launches are counted for
+    pub mime: String,
+    /// The query typed before the chooser was opened
+    pub query: String,
+    /// Best first
+    handlers: Vec<AppEntry>,
+}
+
+impl OpenWith {
+    /// Lists the applications associated with `mime_types`, the file's type
+    /// followed by the ones it is a subclass of. Those used most for files
+    /// of this type come first, the default application among equal ones.
+    pub fn new(
+        file: PathBuf,
+        mime_types: &[String],
+        query: String,
+        mime_apps: &MimeApps,
+        mime_database: &MimeDatabase,
+        apps: &[AppEntry],
+        database: &Database,
+    ) -> Self {
+        let mime = mime_types.first().cloned().unwrap_or_default();
+        let mut handlers: Vec<AppEntry> =
+            mime_apps.handlers(mime_types, apps, mime_database).into_iter().cloned().collect();
+        // Stable, so the default and association order break ties
+        handlers.sort_by(|a, b| {
+            database
+                .handler_frecency(&mime, &b.id)
+                .total_cmp(&database.handler_frecency(&mime, &a.id))
+        });
+        Self { file, mime, query, handlers }
+    }
+
+    /// Returns the applications whose name matches `pattern`, best match
+    /// first and in ranking order among equal ones. All of them match an
+    /// empty pattern.
+    pub fn search(&self, pattern: &str, matcher: &dyn Matcher, case: CaseMode) -> Vec<HandlerMatch> {
+        rank_by_match(&self.handlers, |app| app.name.as_str(), pattern, matcher, case)
+            .into_iter()
+            .map(|(app, match_indices)| HandlerMatch { app: app.clone(), match_indices })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatcherKind;
+
+    fn app(id: &str, name: &str) -> AppEntry {
+        AppEntry {
+            exec: "true %f".to_string(),
+            mime_types: vec!["image/png".to_string()],
+            ..AppEntry::for_test(id, name)
+        }
+    }
+
+    #[test]
+    fn ranks_by_use_for_the_type() {
+        let apps = [app("eog.desktop", "Image Viewer"), app("gimp.desktop", "GIMP"), app("krita.desktop", "Krita")];
+        let mut database = Database::new();
+        let mut record = |mime: &str, id: &str| {
+            let usage = database.handlers.entry(mime.to_string()).or_default().entry(id.to_string()).or_default();
+            usage.launch_count += 1;
+            usage.last_launched = Some(chrono::Utc::now());
+        };
+        record("image/png", "krita.desktop");
+        // Launches for other types don't count
+        record("image/jpeg", "gimp.desktop");
+        record("image/jpeg", "gimp.desktop");
+
+        let types = ["image/png".to_string()];
+        let open_with = OpenWith::new(PathBuf::from("a.png"), &types, String::new(), &MimeApps::default(), &MimeDatabase::default(), &apps, &database);
+        let matcher = MatcherKind::Substring.build();
+        let ids = |pattern: &str| -> Vec<String> {
+            open_with
+                .search(pattern, matcher.as_ref(), CaseMode::Smart)
+                .into_iter()
+                .map(|found| found.app.id)
+                .collect()
+        };
+        assert_eq!(ids(""), ["krita.desktop", "eog.desktop", "gimp.desktop"]);
+        assert_eq!(ids("view"), ["eog.desktop"]);
+    }
+}
@@ -1,4 +1,10 @@
+use crate::matcher::{CaseMode, Matcher, MatcherKind};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::env;
+use std::ffi::{CStr, CString, OsStr};
 use std::fs;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 
@@ -7,13 +13,114 @@ pub struct PathCompletion {
     pub path: PathBuf,
     pub display_name: String,
     pub is_dir: bool,
+    /// Launched directly rather than opened with an application
+    pub is_executable: bool,
+    /// Character indices in `display_name` of the characters the typed
+    /// components matched
+    pub match_indices: Vec<usize>,
 }
 
-pub struct PathCompleter;
+/// Which entries path mode lists; directories are always listed unless
+/// they are all it lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathFilter {
+    /// Every file (the default)
+    #[default]
+    All,
+    /// Executables, which are launched rather than opened
+    Executables,
+    /// Only directories
+    Directories,
+}
+
+impl PathFilter {
+    const ALL: [PathFilter; 3] = [PathFilter::All, PathFilter::Executables, PathFilter::Directories];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PathFilter::All => "all files",
+            PathFilter::Executables => "executables",
+            PathFilter::Directories => "directories",
+        }
+    }
+
+    /// Returns the next filter, wrapping around, for cycling at runtime.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|filter| filter == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn allows(&self, is_dir: bool, is_executable: bool) -> bool {
+        match self {
+            PathFilter::All => true,
+            PathFilter::Executables => is_dir || is_executable,
+            PathFilter::Directories => is_dir,
+        }
+    }
+}
+
+/// Directories followed per fuzzy-matched component, best first, so a
+/// vague query like `/u/s/a` doesn't walk half the file system
+const MAX_BRANCHES: usize = 20;
+
+/// A directory or file reached by the components matched so far.
+#[derive(Debug, Clone)]
+struct Candidate {
+    path: PathBuf,
+    score: i64,
+    /// Matched character indices per fuzzy-matched component, keyed by the
+    /// component's position from the end of the path
+    highlights: Vec<(usize, Vec<usize>)>,
+}
+
+/// Completes paths component by component: each typed component names an
+/// existing directory or is fuzzy matched against the entries of the
+/// directories matched so far, like zsh's partial-word completion, so
+/// `~/pr/ex/tar` reaches `~/projects/exek/target`.
+#[derive(Debug, Clone, Copy)]
+pub struct PathCompleter {
+    matcher: MatcherKind,
+    case: CaseMode,
+    filter: PathFilter,
+    /// List hidden entries even if the typed component doesn't start with
+    /// a dot
+    show_hidden: bool,
+    /// Leave out entries matched by `.gitignore` and `.ignore` files
+    ignore_files: bool,
+}
+
+impl Default for PathCompleter {
+    fn default() -> Self {
+        Self::new(MatcherKind::default(), CaseMode::default())
+    }
+}
 
 impl PathCompleter {
-    pub fn new() -> Self {
-        Self
+    pub fn new(matcher: MatcherKind, case: CaseMode) -> Self {
+        Self {
+            matcher,
+            case,
+            filter: PathFilter::default(),
+            show_hidden: false,
+            ignore_files: false,
+        }
+    }
+
+    pub fn set_matcher(&mut self, matcher: MatcherKind) {
+        self.matcher = matcher;
+    }
+
+    pub fn set_filter(&mut self, filter: PathFilter) {
+        self.filter = filter;
+    }
+
+    pub fn set_show_hidden(&mut self, show_hidden: bool) {
+        self.show_hidden = show_hidden;
+    }
+
+    pub fn set_ignore_files(&mut self, ignore_files: bool) {
+        self.ignore_files = ignore_files;
     }
 
     pub fn is_path_query(query: &str) -> bool {
@@ -21,99 +128,146 @@ impl PathCompleter {
             || query.starts_with("./")
             || query.starts_with("../")
             || query.starts_with('~')
+            || query.starts_with('$')
     }
 
     pub fn complete_path(&self, query: &str) -> Vec<PathCompletion> {
+        self.complete_path_with(query, &|name| env::var(name).ok())
+    }
+
+    /// Like [`complete_path`](Self::complete_path), looking up environment
+    /// variables with `env`.
+    fn complete_path_with(&self, query: &str, env: &dyn Fn(&str) -> Option<String>) -> Vec<PathCompletion> {
         if !Self::is_path_query(query) {
             return Vec::new();
         }
 
-        let expanded = shellexpand::tilde(query);
-        let path_str = expanded.as_ref();
-
-        let (dir_path, prefix) = if path_str.ends_with('/') {
-            (path_str.to_string(), String::new())
-        } else {
-            let path = Path::new(path_str);
-            if let Some(parent) = path.parent() {
-                let parent_str = if parent.as_os_str().is_empty() {
-                    ".".to_string()
-                } else {
-                    parent.to_string_lossy().to_string()
-                };
-                let file_name = path.file_name()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .unwrap_or_default();
-                (parent_str, file_name)
-            } else {
-                (".".to_string(), path_str.to_string())
-            }
+        let expanded = expand_path_with(query, env);
+        let (root, rest) = match expanded.strip_prefix('/') {
+            Some(rest) => (PathBuf::from("/"), rest),
+            None => (PathBuf::new(), expanded.as_str()),
         };
+        let mut components: Vec<&str> = rest.split('/').collect();
+        let last = components.pop().unwrap_or_default();
+        components.retain(|component| !component.is_empty());
 
-        let mut completions = Vec::new();
+        let prefixes = typed_prefixes(query, env);
+        let matcher = self.matcher.build();
+        let mut parents = vec![Candidate { path: root, score: 0, highlights: Vec::new() }];
+        for (index, component) in components.iter().enumerate() {
+            // The last component comes after the directories
+            let depth = components.len() - index;
+            parents = self.descend(&parents, component, depth, matcher.as_ref());
+        }
 
-        if let Ok(entries) = fs::read_dir(&dir_path) {
+        let mut completions: Vec<(i64, PathCompletion)> = Vec::new();
+        for dir in &parents {
+            let Ok(entries) = fs::read_dir(&dir.path) else {
+                continue;
+            };
+            let ignore = self.ignore_rules(&dir.path);
             for entry in entries.flatten() {
-                if let Ok(metadata) = entry.metadata() {
-                    let file_name = entry.file_name().to_string_lossy().to_string();
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let full_path = entry.path();
+                let is_dir = full_path.is_dir();
+                let is_executable = !is_dir && is_executable(&full_path);
+                if !self.filter.allows(is_dir, is_executable)
+                    || !self.lists(&file_name, last)
+                    || ignore.is_ignored(&file_name, is_dir)
+                {
+                    continue;
+                }
 
-                    if !file_name.starts_with(&prefix) {
+                let mut candidate = dir.clone();
+                if !last.is_empty() {
+                    let Some((score, indices)) = self.match_component(matcher.as_ref(), &file_name, last) else {
                         continue;
-                    }
-
-                    let is_dir = metadata.is_dir();
-                    let is_executable = self.is_executable(&entry.path());
-
-                    if is_dir || is_executable {
-                        let full_path = entry.path();
-                        let display_name = if query.starts_with('~') {
-                            let home = dirs::home_dir().unwrap_or_default();
-                            if let Ok(relative) = full_path.strip_prefix(&home) {
-                                format!("~/{}", relative.display())
-                            } else {
-                                full_path.to_string_lossy().to_string()
-                            }
-                        } else {
-                            full_path.to_string_lossy().to_string()
-                        };
-
-                        completions.push(PathCompletion {
-                            path: full_path,
-                            display_name,
-                            is_dir,
-                        });
-                    }
+                    };
+                    candidate.score += score;
+                    candidate.highlights.push((0, indices));
                 }
+
+                let display_name = display_name(&prefixes, &full_path);
+                let match_indices = highlight_indices(&display_name, &candidate.highlights);
+
+                completions.push((
+                    candidate.score,
+                    PathCompletion {
+                        path: full_path,
+                        display_name,
+                        is_dir,
+                        is_executable,
+                        match_indices,
+                    },
+                ));
             }
         }
 
-        completions.sort_by(|a, b| {
-            a.is_dir.cmp(&b.is_dir).reverse()
+        completions.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score)
+                .then_with(|| a.is_dir.cmp(&b.is_dir).reverse())
                 .then_with(|| a.display_name.cmp(&b.display_name))
         });
 
-        completions
+        completions.into_iter().map(|(_, completion)| completion).collect()
     }
 
-    fn is_executable(&self, path: &Path) -> bool {
-        if !path.is_file() {
-            return false;
+    /// Follows `component` from each of `dirs`: into the directory of that
+    /// name if there is one, otherwise into every subdirectory it fuzzy
+    /// matches.
+    fn descend(&self, dirs: &[Candidate], component: &str, depth: usize, matcher: &dyn Matcher) -> Vec<Candidate> {
+        let mut next = Vec::new();
+        for dir in dirs {
+            let exact = dir.path.join(component);
+            if component == "." || component == ".." || exact.is_dir() {
+                next.push(Candidate { path: exact, ..dir.clone() });
+                continue;
+            }
+
+            let Ok(entries) = fs::read_dir(&dir.path) else {
+                continue;
+            };
+            let ignore = self.ignore_rules(&dir.path);
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !self.lists(&name, component) || ignore.is_ignored(&name, true) {
+                    continue;
+                }
+                if let Some((score, indices)) = self.match_component(matcher, &name, component) {
+                    let mut highlights = dir.highlights.clone();
+                    highlights.push((depth, indices));
+                    next.push(Candidate { path, score: dir.score + score, highlights });
+                }
+            }
         }
 
-        if let Ok(metadata) = fs::metadata(path) {
-            let permissions = metadata.permissions();
-            let mode = permissions.mode();
+        next.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        next.truncate(MAX_BRANCHES);
+        next
+    }
 
-            let user_exec = mode & 0o100 != 0;
-            let group_exec = mode & 0o010 != 0;
-            let other_exec = mode & 0o001 != 0;
+    /// Whether an entry named `name` may match `component`: hidden entries
+    /// only do if they are shown or the component starts with a dot.
+    fn lists(&self, name: &str, component: &str) -> bool {
+        self.show_hidden || !name.starts_with('.') || component.starts_with('.')
+    }
 
-            user_exec || group_exec || other_exec
+    fn ignore_rules(&self, dir: &Path) -> IgnoreRules {
+        if self.ignore_files {
+            IgnoreRules::for_dir(dir)
         } else {
-            false
+            IgnoreRules::default()
         }
     }
 
+    fn match_component(&self, matcher: &dyn Matcher, name: &str, component: &str) -> Option<(i64, Vec<usize>)> {
+        matcher.match_indices(name, component, self.case.is_sensitive(component))
+    }
+
     pub fn apply_completion(_query: &str, completion: &PathCompletion) -> String {
         let mut result = completion.display_name.clone();
 
@@ -124,3 +278,287 @@ impl PathCompleter {
         result
     }
 }
+
+/// Expands a leading `~` or `~user` and environment variables (`$VAR` and
+/// `${VAR}`) in a typed path, as a shell would. Unknown users and unset
+/// variables are left as typed.
+pub fn expand_path(query: &str) -> String {
+    expand_path_with(query, &|name| env::var(name).ok())
+}
+
+/// Like [`expand_path`], looking up environment variables with `env`.
+fn expand_path_with(query: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    let (home, rest) = match query.strip_prefix('~') {
+        Some(after) => {
+            let end = after.find('/').unwrap_or(after.len());
+            let user = &after[..end];
+            let home = if user.is_empty() { dirs::home_dir() } else { user_home(user) };
+            match home {
+                Some(home) => (home.to_string_lossy().to_string(), &after[end..]),
+                None => (String::new(), query),
+            }
+        }
+        None => (String::new(), query),
+    };
+    // Only what follows the home directory, which may contain a `$` itself
+    home + &shellexpand::env_with_context_no_errors(rest, env)
+}
+
+/// Looks up the home directory of `user` in the passwd database.
+fn user_home(user: &str) -> Option<PathBuf> {
+    let name = CString::new(user).ok()?;
+    // getpwnam_r fills `passwd` with pointers into `buffer`, which is grown
+    // until the entry fits
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let status = unsafe {
+            libc::getpwnam_r(name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+        if status == libc::ERANGE {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        if status != 0 || result.is_null() {
+            return None;
+        }
+        let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+        return Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())));
+    }
+}
+
+/// Returns the parts of `query` that end at a component boundary, longest
+/// first, with what each expands to.
+fn typed_prefixes<'a>(query: &'a str, env: &dyn Fn(&str) -> Option<String>) -> Vec<(&'a str, PathBuf)> {
+    let mut ends: Vec<usize> = query.match_indices('/').map(|(index, _)| index + 1).collect();
+    ends.push(query.len());
+    ends.dedup();
+    ends.iter()
+        .rev()
+        .map(|&end| (&query[..end], PathBuf::from(expand_path_with(&query[..end], env))))
+        .collect()
+}
+
+/// Shows `path` as the user typed it: the longest of the typed `prefixes`
+/// that expands to an ancestor of `path` is kept unexpanded, so completions
+/// for `$HOME/bi` read `$HOME/bin` rather than `/home/alice/bin`.
+fn display_name(prefixes: &[(&str, PathBuf)], path: &Path) -> String {
+    for (typed, expanded) in prefixes {
+        let Ok(rest) = path.strip_prefix(expanded) else {
+            continue;
+        };
+        let mut name = typed.to_string();
+        if !rest.as_os_str().is_empty() {
+            if !name.ends_with('/') {
+                name.push('/');
+            }
+            name.push_str(&rest.to_string_lossy());
+        }
+        return name;
+    }
+    path.to_string_lossy().to_string()
+}
+
+/// The ignore files that apply to the entries of a directory, like ripgrep
+/// reads them: `.ignore` files in the directory and its ancestors, and
+/// `.gitignore` files up to the root of the git repository it is in.
+#[derive(Default)]
+struct IgnoreRules {
+    dir: PathBuf,
+    /// Most important first
+    files: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    fn for_dir(dir: &Path) -> Self {
+        // Ignore files match against absolute paths
+        let Ok(dir) = dir.canonicalize() else {
+            return Self::default();
+        };
+        let repo = dir.ancestors().find(|ancestor| ancestor.join(".git").exists());
+
+        let mut files = Vec::new();
+        for ancestor in dir.ancestors() {
+            files.extend(Self::read(ancestor, &ancestor.join(".ignore")));
+            if let Some(repo) = repo.filter(|repo| ancestor.starts_with(repo)) {
+                files.extend(Self::read(ancestor, &ancestor.join(".gitignore")));
+                if ancestor == repo {
+                    files.extend(Self::read(ancestor, &ancestor.join(".git/info/exclude")));
+                }
+            }
+        }
+        Self { dir, files }
+    }
+
+    fn read(root: &Path, path: &Path) -> Option<Gitignore> {
+        if !path.is_file() {
+            return None;
+        }
+        let mut builder = GitignoreBuilder::new(root);
+        builder.add(path);
+        builder.build().ok().filter(|rules| !rules.is_empty())
+    }
+
+    /// Whether the most important rule matching the entry `name` ignores it.
+    fn is_ignored(&self, name: &str, is_dir: bool) -> bool {
+        let path = self.dir.join(name);
+        self.files
+            .iter()
+            .map(|rules| rules.matched(&path, is_dir))
+            .find(|found| !found.is_none())
+            .is_some_and(|found| found.is_ignore())
+    }
+}
+
+/// Whether `path` is a file with any execute permission bit set.
+pub fn is_executable(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+
+    if let Ok(metadata) = fs::metadata(path) {
+        let permissions = metadata.permissions();
+        let mode = permissions.mode();
+
+        let user_exec = mode & 0o100 != 0;
+        let group_exec = mode & 0o010 != 0;
+        let other_exec = mode & 0o001 != 0;
+
+        user_exec || group_exec || other_exec
+    } else {
+        false
+    }
+}
+
+/// Maps the matched indices of each component, keyed by position from the
+/// end, to character indices in `display_name`.
+fn highlight_indices(display_name: &str, highlights: &[(usize, Vec<usize>)]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for part in display_name.split('/') {
+        starts.push(offset);
+        offset += part.chars().count() + 1;
+    }
+    starts.reverse();
+
+    let mut indices: Vec<usize> = highlights
+        .iter()
+        .filter_map(|(depth, indices)| Some((starts.get(*depth)?, indices)))
+        .flat_map(|(start, indices)| indices.iter().map(move |index| start + index))
+        .collect();
+    indices.sort_unstable();
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn fuzzy_matches_every_component() {
+        let root = std::env::temp_dir().join(format!("exek-path-completion-{}", std::process::id()));
+        for dir in ["projects/exek/target", "projects/exek/src", "private", "pictures"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let completer = PathCompleter::default();
+        let query = format!("{}/pr/ex/tar", root.display());
+        let completions = completer.complete_path(&query);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(completions.len(), 1);
+        let completion = &completions[0];
+        assert_eq!(completion.path, root.join("projects/exek/target"));
+
+        let highlighted: String = completion
+            .display_name
+            .chars()
+            .enumerate()
+            .filter(|(index, _)| completion.match_indices.contains(index))
+            .map(|(_, c)| c)
+            .collect();
+        assert_eq!(highlighted, "prextar");
+    }
+
+    #[test]
+    fn filters_hidden_ignored_and_by_type() {
+        let root = std::env::temp_dir().join(format!("exek-path-filter-{}", std::process::id()));
+        for dir in [".git", ".config", "src", "target"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("build.log"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        fs::write(root.join("run.sh"), "").unwrap();
+        fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let names = |completer: &PathCompleter, last: &str| -> Vec<String> {
+            let query = format!("{}/{}", root.display(), last);
+            let mut names: Vec<String> = completer
+                .complete_path(&query)
+                .into_iter()
+                .map(|completion| completion.path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let mut completer = PathCompleter::default();
+        let default = names(&completer, "");
+        let dotted = names(&completer, ".gi");
+        completer.set_show_hidden(true);
+        let hidden = names(&completer, "");
+        completer.set_show_hidden(false);
+        completer.set_ignore_files(true);
+        let ignored = names(&completer, "");
+        completer.set_ignore_files(false);
+        completer.set_filter(PathFilter::Executables);
+        let executables = names(&completer, "");
+        completer.set_filter(PathFilter::Directories);
+        let directories = names(&completer, "");
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(default, ["build.log", "notes.txt", "run.sh", "src", "target"]);
+        assert_eq!(dotted, [".git", ".gitignore"]);
+        assert_eq!(hidden, [".config", ".git", ".gitignore", "build.log", "notes.txt", "run.sh", "src", "target"]);
+        assert_eq!(ignored, ["notes.txt", "run.sh", "src"]);
+        assert_eq!(executables, ["run.sh", "src", "target"]);
+        assert_eq!(directories, ["src", "target"]);
+    }
+
+    /// The name and home directory of the user running the tests.
+    fn current_user() -> (String, String) {
+        // getpwuid returns a pointer into static storage, copied right away
+        let passwd = unsafe { libc::getpwuid(libc::getuid()) };
+        assert!(!passwd.is_null());
+        let field = |field: *const libc::c_char| unsafe { CStr::from_ptr(field) }.to_string_lossy().to_string();
+        unsafe { (field((*passwd).pw_name), field((*passwd).pw_dir)) }
+    }
+
+    #[test]
+    fn expands_variables_and_users() {
+        let root = std::env::temp_dir().join(format!("exek-path-expand-{}", std::process::id()));
+        fs::create_dir_all(root.join("projects")).unwrap();
+        let variables = HashMap::from([("EXEK_TEST_ROOT", root.display().to_string())]);
+        let env = |name: &str| variables.get(name).cloned();
+
+        assert!(PathCompleter::is_path_query("$EXEK_TEST_ROOT/"));
+        assert_eq!(expand_path_with("${EXEK_TEST_ROOT}/bin", &env), format!("{}/bin", root.display()));
+        assert_eq!(expand_path_with("$EXEK_TEST_UNSET/bin", &env), "$EXEK_TEST_UNSET/bin");
+        let (user, home) = current_user();
+        assert_eq!(expand_path(&format!("~{}/bin", user)), format!("{}/bin", home));
+        assert_eq!(expand_path("~no-such-user-exek/bin"), "~no-such-user-exek/bin");
+
+        let completions = PathCompleter::default().complete_path_with("$EXEK_TEST_ROOT/proj", &env);
+        let exact = PathCompleter::default().complete_path_with("$EXEK_TEST_ROOT", &env);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].path, root.join("projects"));
+        assert_eq!(completions[0].display_name, "$EXEK_TEST_ROOT/projects");
+        assert_eq!(PathCompleter::apply_completion("", &completions[0]), "$EXEK_TEST_ROOT/projects/");
+        assert!(exact.iter().any(|completion| completion.display_name == "$EXEK_TEST_ROOT"));
+    }
+}
@@ -0,0 +1,960 @@
+use crate::matcher::{chars_equal, contiguous_score, find_chars, CaseMode, Matcher};
+use crate::normalize::Normalized;
+use std::cell::OnceCell;
+
+/// How a single query term is matched against a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermKind {
+    /// `foo`: fuzzy match
+    Fuzzy,
+    /// `'foo`: substring match
+    Exact,
+    /// `^foo`: field starts with the term
+    Prefix,
+    /// `foo$`: field ends with the term
+    Suffix,
+    /// `^foo$`: field equals the term
+    Equal,
+    /// `exec:code*`: shell-style glob over the whole field, only available
+    /// in scoped terms
+    Glob,
+    /// With the regex matcher, every token is a regular expression taken
+    /// as typed, without fzf's operators
+    Regex,
+}
+
+/// The field a `scope:term` filter is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Name,
+    Exec,
+    Comment,
+    Keyword,
+    Category,
+    /// Path of the desktop file; only searched by `file:` filters
+    File,
+}
+
+impl Scope {
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "name" => Some(Scope::Name),
+            "exec" => Some(Scope::Exec),
+            "comment" => Some(Scope::Comment),
+            "kw" | "keyword" => Some(Scope::Keyword),
+            "cat" | "category" => Some(Scope::Category),
+            "file" => Some(Scope::File),
+            _ => None,
+        }
+    }
+
+    /// Whether unscoped terms search this field.
+    fn searched_by_default(&self) -> bool {
+        *self != Scope::File
+    }
+}
+
+/// A tag identifying a searchable field, used to pick the fields a scoped
+/// term applies to.
+pub trait Field: Copy + PartialEq {
+    fn scope(&self) -> Scope;
+}
+
+impl Field for Scope {
+    fn scope(&self) -> Scope {
+        *self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub text: String,
+    /// `!foo`: the term must not match any field
+    pub negated: bool,
+    /// `cat:foo`: the term only matches the given field and acts as a filter
+    /// that doesn't contribute to the score
+    pub scope: Option<Scope>,
+    pub case_sensitive: bool,
+    /// `text` normalized the same way as the fields it is matched against;
+    /// regexes are kept as typed
+    pub pattern: String,
+}
+
+/// How query terms and fields are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchOptions {
+    pub case: CaseMode,
+    /// Strip diacritics and expand ligatures before matching
+    pub normalize: bool,
+    /// Terms are regular expressions for the regex matcher
+    pub regex: bool,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            case: CaseMode::Smart,
+            normalize: true,
+            regex: false,
+        }
+    }
+}
+
+/// A field's text, normalized on first use for case-folded and for
+/// case-sensitive terms.
+struct PreparedField<'a> {
+    text: &'a str,
+    normalize: bool,
+    folded: OnceCell<Normalized>,
+    cased: OnceCell<Normalized>,
+}
+
+impl<'a> PreparedField<'a> {
+    fn new(text: &'a str, normalize: bool) -> Self {
+        Self {
+            text,
+            normalize,
+            folded: OnceCell::new(),
+            cased: OnceCell::new(),
+        }
+    }
+
+    fn get(&self, case_sensitive: bool) -> &Normalized {
+        if case_sensitive {
+            self.cased.get_or_init(|| Normalized::new(self.text, false, self.normalize))
+        } else {
+            self.folded.get_or_init(|| Normalized::new(self.text, true, self.normalize))
+        }
+    }
+}
+
+/// A parsed query in fzf's extended search syntax: whitespace separated
+/// terms must all match, and terms joined by `|` form an OR group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>,
+    normalize: bool,
+}
+
+/// The result of matching a [`Query`] against the fields of an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch<F> {
+    pub score: i64,
+    /// Field with the highest scoring match, `None` if only negated terms
+    /// were given
+    pub field: Option<F>,
+    /// Character indices in `field` matched by any term
+    pub indices: Vec<usize>,
+}
+
+impl Term {
+    pub fn new(kind: TermKind, text: &str, negated: bool, scope: Option<Scope>, options: &MatchOptions) -> Self {
+        // Folding or normalizing a regex changes its meaning (`\D` is not
+        // `\d`), so the regex matcher handles case itself
+        if kind == TermKind::Regex {
+            return Self {
+                kind,
+                text: text.to_string(),
+                negated,
+                scope,
+                case_sensitive: options.case.is_sensitive(&regex_literals(text)),
+                pattern: text.to_string(),
+            };
+        }
+
+        let case_sensitive = options.case.is_sensitive(text);
+        Self {
+            kind,
+            text: text.to_string(),
+            negated,
+            scope,
+            case_sensitive,
+            pattern: Normalized::new(text, !case_sensitive, options.normalize).text,
+        }
+    }
+
+    fn parse(token: &str, options: &MatchOptions) -> Option<Self> {
+        // `^`, `$`, `!` and `'` mean something else in a regex, so only
+        // scopes are parsed
+        if options.regex {
+            let (scope, text) = split_scope(token);
+            return (!text.is_empty()).then(|| Self::new(TermKind::Regex, text, false, scope, options));
+        }
+
+        let (negated, token) = match token.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, token),
+        };
+
+        let (scope, token) = split_scope(token);
+
+        let (kind, text) = if let Some(rest) = token.strip_prefix('\'') {
+            (TermKind::Exact, rest)
+        } else if let Some(rest) = token.strip_prefix('^') {
+            match rest.strip_suffix('$') {
+                Some(inner) if !inner.is_empty() => (TermKind::Equal, inner),
+                _ => (TermKind::Prefix, rest),
+            }
+        } else if let Some(rest) = token.strip_suffix('$').filter(|rest| !rest.is_empty()) {
+            (TermKind::Suffix, rest)
+        } else if scope.is_some() && token.contains(['*', '?']) {
+            (TermKind::Glob, token)
+        } else if negated || scope.is_some() {
+            // Like fzf, `!foo` excludes exact rather than fuzzy matches.
+            // Scoped filters are exact too, to narrow without fuzzy noise.
+            (TermKind::Exact, token)
+        } else {
+            (TermKind::Fuzzy, token)
+        };
+
+        if text.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(kind, text, negated, scope, options))
+    }
+
+    /// Whether everything this term matches is also matched by `previous`,
+    /// e.g. `firef` after `fire`. Only terms extended without changing their
+    /// kind qualify; negated, suffix, equal and glob terms must be unchanged.
+    fn refines(&self, previous: &Term, matcher: &dyn Matcher) -> bool {
+        if self == previous {
+            return true;
+        }
+
+        let extendable = match self.kind {
+            TermKind::Fuzzy => matcher.narrows(),
+            TermKind::Exact | TermKind::Prefix => true,
+            TermKind::Suffix | TermKind::Equal | TermKind::Glob | TermKind::Regex => false,
+        };
+
+        extendable
+            && !self.negated
+            && !previous.negated
+            && self.kind == previous.kind
+            && self.scope == previous.scope
+            // A case-sensitive term matches a subset of the folded one, but
+            // not the other way round
+            && (self.case_sensitive || !previous.case_sensitive)
+            && self.pattern.starts_with(&previous.pattern)
+    }
+
+    /// Matches the term against a field, returning the score and the char
+    /// indices of the match in the original field text. Fuzzy terms use
+    /// `matcher`; the other kinds have fixed semantics. Negation is not
+    /// applied here.
+    fn match_field(&self, field: &PreparedField, matcher: &dyn Matcher) -> Option<(i64, Vec<usize>)> {
+        // Regexes match the field with its case intact
+        let normalized = field.get(self.case_sensitive || self.kind == TermKind::Regex);
+        let (score, indices) = self.match_text(&normalized.text, matcher)?;
+        Some((score, normalized.original_indices(&indices)))
+    }
+
+    fn match_text(&self, text: &str, matcher: &dyn Matcher) -> Option<(i64, Vec<usize>)> {
+        if matches!(self.kind, TermKind::Fuzzy | TermKind::Regex) {
+            return matcher.match_indices(text, &self.pattern, self.case_sensitive);
+        }
+
+        let haystack: Vec<char> = text.chars().collect();
+        let needle: Vec<char> = self.pattern.chars().collect();
+        let case_sensitive = self.case_sensitive;
+
+        if self.kind == TermKind::Glob {
+            return glob_match(&needle, &haystack, case_sensitive).then(|| (1, Vec::new()));
+        }
+
+        if needle.len() > haystack.len() {
+            return None;
+        }
+
+        let last_start = haystack.len() - needle.len();
+        let start = match self.kind {
+            TermKind::Exact => find_chars(&haystack, &needle, 0, case_sensitive)?,
+            TermKind::Prefix => find_chars(&haystack[..needle.len()], &needle, 0, case_sensitive)?,
+            TermKind::Suffix => find_chars(&haystack, &needle, last_start, case_sensitive)?,
+            TermKind::Equal if last_start == 0 => find_chars(&haystack, &needle, 0, case_sensitive)?,
+            _ => return None,
+        };
+
+        Some((contiguous_score(&haystack, start, needle.len()), (start..start + needle.len()).collect()))
+    }
+}
+
+/// Matches a shell-style glob where `*` matches any run of characters and
+/// `?` a single character, anchored at both ends. On a mismatch only the
+/// most recent `*` takes one more character, so matching takes
+/// `O(pattern × text)` time rather than backtracking exponentially.
+fn glob_match(pattern: &[char], text: &[char], case_sensitive: bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, t));
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if chars_equal(text[t], *c, case_sensitive) => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((star_p, star_t)) = star else {
+                    return false;
+                };
+                // Let the `*` swallow one more character
+                p = star_p;
+                t = star_t + 1;
+                star = Some((star_p, t));
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the characters of a regex that stand for themselves, leaving out
+/// escapes like `\W` or `\p{Lu}`, so smart case only looks at letters
+/// meant literally.
+fn regex_literals(pattern: &str) -> String {
+    let mut literals = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            literals.push(c);
+            continue;
+        }
+        // Unicode classes: `\pL` or `\p{Letter}`
+        if let Some('p' | 'P') = chars.next() {
+            if chars.next() == Some('{') {
+                chars.by_ref().find(|c| *c == '}');
+            }
+        }
+    }
+    literals
+}
+
+/// Splits a `scope:` prefix off a token, if it names a scope.
+fn split_scope(token: &str) -> (Option<Scope>, &str) {
+    match token.split_once(':') {
+        Some((prefix, rest)) => match Scope::from_prefix(prefix) {
+            Some(scope) => (Some(scope), rest),
+            None => (None, token),
+        },
+        None => (None, token),
+    }
+}
+
+/// Splits the query at unescaped whitespace; `\ ` keeps a literal space.
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&' ') {
+            current.push(' ');
+            chars.next();
+        } else if c.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+impl Query {
+    pub fn parse(input: &str, options: &MatchOptions) -> Self {
+        let mut groups: Vec<Vec<Term>> = Vec::new();
+        let mut join_next = false;
+
+        for token in tokenize(input) {
+            if token == "|" && !options.regex {
+                join_next = !groups.is_empty();
+                continue;
+            }
+
+            let Some(term) = Term::parse(&token, options) else {
+                continue;
+            };
+
+            match groups.last_mut() {
+                Some(group) if join_next => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            join_next = false;
+        }
+
+        Self {
+            groups,
+            normalize: options.normalize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the term if the query is a single plain fuzzy term.
+    pub fn single_fuzzy_term(&self) -> Option<&Term> {
+        match self.groups.as_slice() {
+            [group] => match group.as_slice() {
+                [term] if term.kind == TermKind::Fuzzy && !term.negated && term.scope.is_none() => Some(term),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether every entry matching this query also matches `previous`, so
+    /// this query only needs to be evaluated against the previous matches.
+    /// Holds when each term of `previous` was extended in place and any new
+    /// terms are ANDed on; a term added to an OR group widens the query.
+    pub fn refines(&self, previous: &Query, matcher: &dyn Matcher) -> bool {
+        self.normalize == previous.normalize
+            && self.groups.len() >= previous.groups.len()
+            && self.groups.iter().zip(&previous.groups).all(|(group, previous_group)| {
+                group.len() == previous_group.len()
+                    && group
+                        .iter()
+                        .zip(previous_group)
+                        .all(|(term, previous_term)| term.refines(previous_term, matcher))
+            })
+    }
+
+    /// Matches the query against weighted fields given as `(tag, text,
+    /// weight)`; a field's score is multiplied by its weight. Every group must
+    /// have a matching term, and each group contributes the score of its
+    /// best term. Negated terms match when they match none of the fields.
+    /// Scoped terms only look at fields with that scope and add no score.
+    pub fn evaluate<F: Field>(
+        &self,
+        fields: &[(F, &str, f64)],
+        matcher: &dyn Matcher,
+    ) -> Option<QueryMatch<F>> {
+        let prepared: Vec<PreparedField> = fields
+            .iter()
+            .map(|(_, text, _)| PreparedField::new(text, self.normalize))
+            .collect();
+        let mut score = 0;
+        let mut group_matches: Vec<(F, i64, Vec<usize>)> = Vec::new();
+
+        for group in &self.groups {
+            let mut group_best: Option<(F, i64, Vec<usize>)> = None;
+            let mut group_matched = false;
+
+            for term in group {
+                let in_scope = |field: &F| match term.scope {
+                    Some(scope) => field.scope() == scope,
+                    None => field.scope().searched_by_default(),
+                };
+
+                if term.negated || term.scope.is_some() {
+                    let found = fields
+                        .iter()
+                        .zip(&prepared)
+                        .filter(|((field, _, _), _)| in_scope(field))
+                        .any(|(_, text)| term.match_field(text, matcher).is_some());
+                    group_matched |= found != term.negated;
+                    continue;
+                }
+
+                for ((field, _, weight), text) in fields.iter().zip(&prepared).filter(|((field, _, _), _)| in_scope(field)) {
+                    // A weight of 0 disables the field. Any match in the
+                    // others counts, whatever its score, as extending the
+                    // query can raise a low score again.
+                    if *weight <= 0.0 {
+                        continue;
+                    }
+                    let Some((term_score, indices)) = term.match_field(text, matcher) else {
+                        continue;
+                    };
+                    let weighted = weighted_score(term_score, *weight);
+                    group_matched = true;
+                    // Ties go to the earlier field
+                    if group_best.as_ref().is_none_or(|(_, best, _)| weighted > *best) {
+                        group_best = Some((*field, weighted, indices));
+                    }
+                }
+            }
+
+            if !group_matched {
+                return None;
+            }
+            if let Some(best) = group_best {
+                score += best.1;
+                group_matches.push(best);
+            }
+        }
+
+        let field = group_matches
+            .iter()
+            .fold(None::<&(F, i64, Vec<usize>)>, |best, m| match best {
+                Some(b) if b.1 >= m.1 => Some(b),
+                _ => Some(m),
+            })
+            .map(|(field, _, _)| *field);
+
+        let mut indices: Vec<usize> = group_matches
+            .iter()
+            .filter(|(f, _, _)| Some(*f) == field)
+            .flat_map(|(_, _, indices)| indices.iter().copied())
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        Some(QueryMatch { score, field, indices })
+    }
+
+    /// Returns the score each field would get on its own: the sum over
+    /// groups of the field's best scoring term, weighted. Fields no term
+    /// scores on are left out. Used to explain rankings, so it doesn't check
+    /// whether the query as a whole matches.
+    pub fn field_scores<F: Field>(&self, fields: &[(F, &str, f64)], matcher: &dyn Matcher) -> Vec<(F, i64)> {
+        fields
+            .iter()
+            .filter(|(field, _, _)| field.scope().searched_by_default())
+            .filter_map(|(field, text, weight)| {
+                let prepared = PreparedField::new(text, self.normalize);
+                let score: i64 = self
+                    .groups
+                    .iter()
+                    .filter_map(|group| {
+                        group
+                            .iter()
+                            .filter(|term| !term.negated && term.scope.is_none())
+                            .filter_map(|term| term.match_field(&prepared, matcher))
+                            .map(|(score, _)| weighted_score(score, *weight))
+                            .max()
+                    })
+                    .filter(|score| *score > 0)
+                    .sum();
+                (score > 0).then_some((*field, score))
+            })
+            .collect()
+    }
+}
+
+fn weighted_score(score: i64, weight: f64) -> i64 {
+    (score as f64 * weight) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatcherKind;
+
+    fn parse(query: &str) -> Query {
+        Query::parse(query, &MatchOptions::default())
+    }
+
+    fn term(kind: TermKind, text: &str, negated: bool) -> Term {
+        Term::new(kind, text, negated, None, &MatchOptions::default())
+    }
+
+    fn scoped(scope: Scope, kind: TermKind, text: &str, negated: bool) -> Term {
+        Term::new(kind, text, negated, Some(scope), &MatchOptions::default())
+    }
+
+    fn matches(query: &str, text: &str) -> bool {
+        let matcher = MatcherKind::Skim.build();
+        parse(query).evaluate(&[(Scope::Name, text, 1.0)], matcher.as_ref()).is_some()
+    }
+
+    fn app_fields() -> [(Scope, &'static str, f64); 5] {
+        [
+            (Scope::Name, "Visual Studio Code", 1.0),
+            (Scope::Exec, "code", 1.0),
+            (Scope::Keyword, "editor", 0.5),
+            (Scope::Category, "Development", 0.33),
+            (Scope::File, "/usr/share/applications/code.desktop", 1.0),
+        ]
+    }
+
+    fn matches_app(query: &str) -> bool {
+        let matcher = MatcherKind::Skim.build();
+        parse(query).evaluate(&app_fields(), matcher.as_ref()).is_some()
+    }
+
+    #[test]
+    fn parses_each_operator() {
+        let query = parse("fire 'exact ^pre suf$ ^eq$ !not");
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![term(TermKind::Fuzzy, "fire", false)],
+                vec![term(TermKind::Exact, "exact", false)],
+                vec![term(TermKind::Prefix, "pre", false)],
+                vec![term(TermKind::Suffix, "suf", false)],
+                vec![term(TermKind::Equal, "eq", false)],
+                vec![term(TermKind::Exact, "not", true)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_negated_anchors() {
+        let query = parse("!^pre !suf$ !'exact");
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![term(TermKind::Prefix, "pre", true)],
+                vec![term(TermKind::Suffix, "suf", true)],
+                vec![term(TermKind::Exact, "exact", true)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_or_groups() {
+        let query = parse("a | b c | | d");
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![term(TermKind::Fuzzy, "a", false), term(TermKind::Fuzzy, "b", false)],
+                vec![term(TermKind::Fuzzy, "c", false), term(TermKind::Fuzzy, "d", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_empty_terms_and_dangling_pipes() {
+        assert!(parse("  ").is_empty());
+        assert!(parse("^ ! ' !^").is_empty());
+        assert_eq!(parse("| a |").groups, vec![vec![term(TermKind::Fuzzy, "a", false)]]);
+    }
+
+    #[test]
+    fn escaped_space_stays_in_term() {
+        assert_eq!(
+            parse(r"'visual\ studio").groups,
+            vec![vec![term(TermKind::Exact, "visual studio", false)]]
+        );
+    }
+
+    #[test]
+    fn fuzzy_term() {
+        assert!(matches("ffx", "Firefox"));
+        assert!(!matches("xff", "Firefox"));
+    }
+
+    #[test]
+    fn exact_term() {
+        assert!(matches("'fox", "Firefox"));
+        assert!(!matches("'ffx", "Firefox"));
+    }
+
+    #[test]
+    fn prefix_term() {
+        assert!(matches("^fire", "Firefox"));
+        assert!(!matches("^fox", "Firefox"));
+    }
+
+    #[test]
+    fn suffix_term() {
+        assert!(matches("fox$", "Firefox"));
+        assert!(!matches("fire$", "Firefox"));
+    }
+
+    #[test]
+    fn equal_term() {
+        assert!(matches("^firefox$", "Firefox"));
+        assert!(!matches("^fire$", "Firefox"));
+    }
+
+    #[test]
+    fn negated_term() {
+        assert!(matches("!chrome", "Firefox"));
+        assert!(!matches("!fox", "Firefox"));
+        assert!(!matches("!^fire", "Firefox"));
+        assert!(matches("!^fox", "Firefox"));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(matches("'FOX", "FIREFOX"));
+        assert!(matches("'fox", "FIREFOX"));
+        assert!(!matches("'Fox", "FIREFOX"));
+    }
+
+    #[test]
+    fn and_terms_must_all_match() {
+        assert!(matches("fire fox", "Firefox"));
+        assert!(matches("^fire fox$ !chrome", "Firefox"));
+        assert!(!matches("fire chrome", "Firefox"));
+    }
+
+    #[test]
+    fn or_group_needs_one_match() {
+        assert!(matches("chrome | fire", "Firefox"));
+        assert!(!matches("chrome | opera", "Firefox"));
+        assert!(matches("^web | ^fire fox$", "Firefox"));
+        assert!(!matches("^web | ^fire chrome", "Firefox"));
+    }
+
+    #[test]
+    fn negated_term_in_or_group() {
+        assert!(matches("chrome | !opera", "Firefox"));
+        assert!(!matches("chrome | !fire", "Firefox"));
+    }
+
+    #[test]
+    fn terms_may_match_different_fields() {
+        let matcher = MatcherKind::Skim.build();
+        let fields = [(Scope::Name, "Firefox", 1.0), (Scope::Comment, "Web Browser", 0.5)];
+
+        let result = parse("fire browser").evaluate(&fields, matcher.as_ref()).unwrap();
+        assert_eq!(result.field, Some(Scope::Name));
+        assert_eq!(result.indices, vec![0, 1, 2, 3]);
+
+        assert!(parse("fire !browser").evaluate(&fields, matcher.as_ref()).is_none());
+    }
+
+    #[test]
+    fn indices_are_merged_for_winning_field() {
+        let matcher = MatcherKind::Skim.build();
+        let result = parse("^fire fox$").evaluate(&[(Scope::Name, "Firefox", 1.0)], matcher.as_ref()).unwrap();
+        assert_eq!(result.indices, vec![0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn only_negated_terms_match_without_field() {
+        let matcher = MatcherKind::Skim.build();
+        let result = parse("!chrome").evaluate(&[(Scope::Name, "Firefox", 1.0)], matcher.as_ref()).unwrap();
+        assert_eq!(result.score, 0);
+        assert_eq!(result.field, None);
+        assert!(result.indices.is_empty());
+    }
+
+    #[test]
+    fn parses_scoped_terms() {
+        let query = parse("cat:Development !exec:^code kw:pdf* file:/usr/* foo:bar");
+        assert_eq!(
+            query.groups,
+            vec![
+                vec![scoped(Scope::Category, TermKind::Exact, "Development", false)],
+                vec![scoped(Scope::Exec, TermKind::Prefix, "code", true)],
+                vec![scoped(Scope::Keyword, TermKind::Glob, "pdf*", false)],
+                vec![scoped(Scope::File, TermKind::Glob, "/usr/*", false)],
+                vec![term(TermKind::Fuzzy, "foo:bar", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn scoped_term_only_matches_its_field() {
+        assert!(matches_app("cat:devel"));
+        assert!(!matches_app("name:devel"));
+        assert!(matches_app("exec:^code$"));
+        assert!(!matches_app("cat:editor"));
+        assert!(matches_app("kw:edit"));
+    }
+
+    #[test]
+    fn scoped_terms_are_exact() {
+        assert!(!matches_app("cat:dvlp"));
+        assert!(matches_app("dvlp"));
+    }
+
+    #[test]
+    fn file_field_is_only_searched_by_file_scope() {
+        assert!(!matches_app("'applications"));
+        assert!(matches_app("file:applications"));
+        assert!(matches_app("file:/usr/share/applications/*.desktop"));
+        assert!(!matches_app("file:/usr/share/applications/org.kde*"));
+    }
+
+    #[test]
+    fn glob_term() {
+        assert!(matches_app("exec:c?de"));
+        assert!(matches_app("exec:*"));
+        assert!(!matches_app("exec:c?d"));
+    }
+
+    #[test]
+    fn glob_matching_backtracks_linearly() {
+        let glob = |pattern: &str, text: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let text: Vec<char> = text.chars().collect();
+            glob_match(&pattern, &text, true)
+        };
+        assert!(glob("*a*b", "xaxxb"));
+        assert!(glob("a*b*c", "abbbc"));
+        assert!(glob("**", ""));
+        assert!(glob("*?", "x"));
+        assert!(!glob("*?", ""));
+        assert!(!glob("a*b", "ab c"));
+        assert!(!glob("*a", "ab"));
+
+        // Exponential with naive recursion
+        let long = "a".repeat(200);
+        assert!(!glob("*a*a*a*a*a*a*a*a*b", &long));
+        assert!(glob("*a*a*a*a*a*a*a*a*", &long));
+    }
+
+    #[test]
+    fn negated_scoped_term() {
+        assert!(matches_app("!cat:game"));
+        assert!(!matches_app("!cat:devel"));
+    }
+
+    #[test]
+    fn scoped_terms_combine_with_fuzzy_terms() {
+        let matcher = MatcherKind::Skim.build();
+        let fields = app_fields();
+
+        let filtered = parse("vsc cat:devel").evaluate(&fields, matcher.as_ref()).unwrap();
+        let unfiltered = parse("vsc").evaluate(&fields, matcher.as_ref()).unwrap();
+        assert_eq!(filtered, unfiltered);
+
+        assert!(parse("vsc cat:game").evaluate(&fields, matcher.as_ref()).is_none());
+        assert!(matches_app("cat:game | cat:devel code"));
+    }
+
+    #[test]
+    fn normalized_matching_reports_original_indices() {
+        let matcher = MatcherKind::Skim.build();
+
+        let result = parse("resume").evaluate(&[(Scope::Name, "Résumé Editor", 1.0)], matcher.as_ref()).unwrap();
+        assert_eq!(result.indices, vec![0, 1, 2, 3, 4, 5]);
+
+        let result = parse("'strasse").evaluate(&[(Scope::Name, "Straße", 1.0)], matcher.as_ref()).unwrap();
+        assert_eq!(result.indices, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn normalization_can_be_disabled() {
+        let matcher = MatcherKind::Skim.build();
+        let options = MatchOptions {
+            normalize: false,
+            ..MatchOptions::default()
+        };
+        let query = Query::parse("'resume", &options);
+        assert!(query.evaluate(&[(Scope::Name, "Résumé", 1.0)], matcher.as_ref()).is_none());
+    }
+
+    #[test]
+    fn case_modes() {
+        let matches_with = |case, query: &str| {
+            let matcher = MatcherKind::Skim.build();
+            let options = MatchOptions {
+                case,
+                ..MatchOptions::default()
+            };
+            Query::parse(query, &options)
+                .evaluate(&[(Scope::Name, "Firefox", 1.0)], matcher.as_ref())
+                .is_some()
+        };
+
+        assert!(matches_with(CaseMode::Smart, "fire"));
+        assert!(matches_with(CaseMode::Smart, "Fire"));
+        assert!(!matches_with(CaseMode::Smart, "FIRE"));
+
+        assert!(matches_with(CaseMode::Ignore, "FIRE"));
+
+        assert!(!matches_with(CaseMode::Respect, "fire"));
+        assert!(matches_with(CaseMode::Respect, "Fire"));
+    }
+
+    fn regex_matches(query: &str, text: &str) -> bool {
+        let matcher = MatcherKind::Regex.build();
+        let options = MatchOptions {
+            regex: true,
+            ..MatchOptions::default()
+        };
+        Query::parse(query, &options)
+            .evaluate(&[(Scope::Name, text, 1.0), (Scope::Category, "Network", 1.0)], matcher.as_ref())
+            .is_some()
+    }
+
+    #[test]
+    fn regex_mode_keeps_regex_syntax() {
+        assert_eq!(
+            Query::parse("^fi", &MatchOptions { regex: true, ..MatchOptions::default() }).groups[0][0].kind,
+            TermKind::Regex
+        );
+        assert!(regex_matches("^fi", "Firefox"));
+        assert!(!regex_matches("^fi", "Wifi"));
+        assert!(regex_matches("fox$", "Firefox"));
+        assert!(!regex_matches("fox$", "Foxglove"));
+        assert!(regex_matches("^fi.*x$", "Firefox"));
+
+        // fzf's operators are regex text
+        assert!(!regex_matches("!chrome", "Firefox"));
+        assert!(regex_matches("fire|chrom", "Chromium"));
+        assert!(!regex_matches("'fire", "Firefox"));
+
+        // Scopes still apply
+        assert!(regex_matches("cat:^net", "Firefox"));
+        assert!(!regex_matches("cat:^work", "Firefox"));
+    }
+
+    #[test]
+    fn regex_patterns_are_not_normalized() {
+        let matches_with = |case, query: &str, text: &str| {
+            let matcher = MatcherKind::Regex.build();
+            let options = MatchOptions {
+                case,
+                regex: true,
+                ..MatchOptions::default()
+            };
+            Query::parse(query, &options)
+                .evaluate(&[(Scope::Name, text, 1.0)], matcher.as_ref())
+                .is_some()
+        };
+
+        // Folding would turn these into `\d` and `\w`
+        assert!(!matches_with(CaseMode::Ignore, r"^\D+$", "2048"));
+        assert!(matches_with(CaseMode::Ignore, r"^\D+$", "Firefox"));
+        assert!(!matches_with(CaseMode::Ignore, r"^\W", "Firefox"));
+
+        // The field is still normalized, and case is left to the regex
+        assert!(matches_with(CaseMode::Ignore, "^RESUME", "Résumé Editor"));
+        assert!(!matches_with(CaseMode::Respect, "^RESUME", "Résumé Editor"));
+        assert!(matches_with(CaseMode::Respect, "^Resume", "Résumé Editor"));
+
+        // Escapes don't count as uppercase for smart case
+        assert!(matches_with(CaseMode::Smart, r"\Sfox", "FireFox"));
+        assert!(!matches_with(CaseMode::Smart, r"\SFox", "Firefox"));
+        assert_eq!(regex_literals(r"a\W\p{Lu}\PLb"), "ab");
+    }
+
+    #[test]
+    fn refines_extended_terms() {
+        let skim = MatcherKind::Skim.build();
+        let refines = |query: &str, previous: &str| parse(query).refines(&parse(previous), skim.as_ref());
+
+        assert!(refines("fire", "fir"));
+        assert!(refines("fire fox", "fire"));
+        assert!(refines("^fire", "^fi"));
+        assert!(refines("'fire | chr", "'fi | ch"));
+        assert!(refines("cat:dev", "cat:"));
+        assert!(refines("firE", "fir"));
+
+        // Each of these can match entries the previous query didn't
+        assert!(!refines("fir", "fire"));
+        assert!(!refines("fire | chr", "fire"));
+        assert!(!refines("!fire", "!fi"));
+        assert!(!refines("fire$", "fir$"));
+        assert!(!refines("exec:c?d", "exec:c?"));
+        assert!(!refines("fire", "Fir"));
+        assert!(!refines("kw:x", "kw"));
+
+        let regex = MatcherKind::Regex.build();
+        assert!(!parse("a|b").refines(&parse("a"), regex.as_ref()));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::matcher::{rank_by_match, CaseMode, Matcher};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of queries kept; the oldest are dropped first
+const MAX_QUERIES: usize = 1000;
+
+/// A past query matching a history search.
+#[derive(Debug, Clone)]
+pub struct HistoryMatch {
+    pub query: String,
+    pub match_indices: Vec<usize>,
+}
+
+/// Queries that led to a launch, kept across sessions separately from the
+/// launch history in [`Database`](crate::database::Database) so they can be
+/// recalled in the search box.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct QueryHistory {
+    /// Oldest first, without duplicates
+    queries: Vec<String>,
+    /// Index of the query shown while browsing
+    #[serde(skip)]
+    browsing: Option<usize>,
+    /// What was typed before browsing started
+    #[serde(skip)]
+    draft: String,
+}
+
+impl QueryHistory {
+    pub fn load() -> Result<Self> {
+        let path = Self::history_path()?;
+        if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&data)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::history_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_string_pretty(&self)?;
+        fs::write(&path, data)?;
+        Ok(())
+    }
+
+    /// Adds `query` as the most recent one, moving it there if it was used
+    /// before.
+    pub fn record(&mut self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() {
+            return;
+        }
+        self.queries.retain(|previous| previous != query);
+        self.queries.push(query.to_string());
+        if self.queries.len() > MAX_QUERIES {
+            self.queries.drain(..self.queries.len() - MAX_QUERIES);
+        }
+        self.browsing = None;
+    }
+
+    /// Returns the query before the one shown, starting from the most
+    /// recent. `current` is the search box text; once it was edited,
+    /// browsing starts over from it.
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let index = match self.browsing {
+            Some(index) if self.queries[index] == current => index,
+            _ => {
+                self.draft = current.to_string();
+                self.queries.len()
+            }
+        };
+        let index = index.checked_sub(1)?;
+        self.browsing = Some(index);
+        Some(&self.queries[index])
+    }
+
+    /// Returns the query after the one shown, and what was typed before
+    /// browsing after the most recent one.
+    pub fn next(&mut self, current: &str) -> Option<&str> {
+        let index = self.browsing.filter(|&index| self.queries[index] == current)?;
+        if index + 1 < self.queries.len() {
+            self.browsing = Some(index + 1);
+            Some(&self.queries[index + 1])
+        } else {
+            self.browsing = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Returns the queries matching `pattern`, best match first and the
+    /// most recent first among equal ones. All queries match an empty
+    /// pattern.
+    pub fn search(&self, pattern: &str, matcher: &dyn Matcher, case: CaseMode) -> Vec<HistoryMatch> {
+        rank_by_match(self.queries.iter().rev(), |query| query.as_str(), pattern, matcher, case)
+            .into_iter()
+            .map(|(query, match_indices)| HistoryMatch { query: query.clone(), match_indices })
+            .collect()
+    }
+
+    fn history_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        Ok(config_dir.join("exek").join("query_history.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatcherKind;
+
+    fn history(queries: &[&str]) -> QueryHistory {
+        let mut history = QueryHistory::default();
+        queries.iter().for_each(|query| history.record(query));
+        history
+    }
+
+    #[test]
+    fn records_without_duplicates() {
+        let history = history(&["fire", "~/bin/", " ", "fire"]);
+        assert_eq!(history.queries, ["~/bin/", "fire"]);
+    }
+
+    #[test]
+    fn browses_back_and_forth() {
+        let mut history = history(&["one", "two", "three"]);
+        assert_eq!(history.previous("draft"), Some("three"));
+        assert_eq!(history.previous("three"), Some("two"));
+        assert_eq!(history.previous("two"), Some("one"));
+        assert_eq!(history.previous("one"), None);
+        assert_eq!(history.next("one"), Some("two"));
+        assert_eq!(history.next("two"), Some("three"));
+        assert_eq!(history.next("three"), Some("draft"));
+        assert_eq!(history.next("draft"), None);
+
+        // Editing a recalled query starts over from the newest
+        assert_eq!(history.previous("three"), Some("three"));
+        assert_eq!(history.previous("thre"), Some("three"));
+    }
+
+    #[test]
+    fn searches_best_match_first() {
+        let history = history(&["firefox", "files", "gimp", "fi"]);
+        let matcher = MatcherKind::Substring.build();
+        let found: Vec<String> = history
+            .search("fi", matcher.as_ref(), CaseMode::Smart)
+            .into_iter()
+            .map(|found| found.query)
+            .collect();
+        assert_eq!(found[0], "fi");
+        assert_eq!(found.len(), 3);
+
+        assert_eq!(history.search("", matcher.as_ref(), CaseMode::Smart)[0].query, "fi");
+    }
+}
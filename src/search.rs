@@ -1,113 +1,695 @@
+use crate::config::{EmptyQueryConfig, RankingConfig, SearchConfig};
 use crate::database::Database;
 use crate::desktop_entry::AppEntry;
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
+use crate::matcher::{Matcher, MatcherKind};
+use crate::normalize::Normalized;
+use crate::query::{Field, MatchOptions, Query, Scope};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
 
+/// Candidate count from which entries are scored on all cores; below it
+/// the thread handoff costs more than it saves
+const PARALLEL_THRESHOLD: usize = 2048;
+
+/// Entries scored between the partial results of a streaming search
+const STREAM_CHUNK: usize = 16384;
+
+/// What the empty query lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyView {
+    /// Pinned entries, then the others by frecency
+    #[default]
+    Recent,
+    /// Only pinned entries, in pin order
+    Pinned,
+    /// Every entry alphabetically
+    All,
+    /// Every entry alphabetically within its main category
+    Categories,
+}
+
+/// The field of an [`AppEntry`] a query matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchField {
+    Name,
+    Exec,
+    Comment,
+    Keyword(usize),
+    Category(usize),
+    /// Path of the desktop file, only matched by `file:` filters
+    File,
+}
+
+impl MatchField {
+    /// Returns the text of this field, which match indices refer to.
+    pub fn text<'a>(&self, app: &'a AppEntry) -> &'a str {
+        match self {
+            MatchField::Name => &app.name,
+            MatchField::Exec => exec_name(app),
+            MatchField::Comment => app.comment.as_deref().unwrap_or(""),
+            MatchField::Keyword(i) => app.keywords.get(*i).map(String::as_str).unwrap_or(""),
+            MatchField::Category(i) => app.categories.get(*i).map(String::as_str).unwrap_or(""),
+            MatchField::File => app.desktop_file.to_str().unwrap_or(""),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchField::Name => "name",
+            MatchField::Exec => "exec",
+            MatchField::Comment => "comment",
+            MatchField::Keyword(_) => "keyword",
+            MatchField::Category(_) => "category",
+            MatchField::File => "file",
+        }
+    }
+}
+
+impl Field for MatchField {
+    fn scope(&self) -> Scope {
+        match self {
+            MatchField::Name => Scope::Name,
+            MatchField::Exec => Scope::Exec,
+            MatchField::Comment => Scope::Comment,
+            MatchField::Keyword(_) => Scope::Keyword,
+            MatchField::Category(_) => Scope::Category,
+            MatchField::File => Scope::File,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SearchResult {
-    pub app: AppEntry,
+    pub app: Arc<AppEntry>,
     pub score: i64,
     pub frecency: f64,
+    pub pinned: bool,
+    /// Field the query matched, `None` for the empty-query list
+    pub matched_field: Option<MatchField>,
+    /// Character indices of the matched characters in `matched_field`
+    pub match_indices: Vec<usize>,
+    /// Found by the typo-tolerant fallback rather than the query itself
+    pub approximate: bool,
+    /// How `score` was computed, in explain mode
+    pub explanation: Option<Explanation>,
+    /// Heading the entry is listed under in the grouped empty-query view
+    pub group: Option<&'static str>,
+}
+
+/// The terms of a result's score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// Weighted score of each field the query matched on its own
+    pub fields: Vec<(MatchField, i64)>,
+    /// Score of the query match: the best field score of each group, summed
+    pub match_score: i64,
+    pub name_boost: i64,
+    pub pin_boost: i64,
+    pub frecency_bonus: i64,
+}
+
+/// Returns the file name of the executable in the Exec line.
+#[allow(clippy::double_ended_iterator_last)]
+fn exec_name(app: &AppEntry) -> &str {
+    let exec_base = app.exec.split_whitespace().next().unwrap_or("");
+    exec_base.split('/').last().unwrap_or("")
+}
+
+/// The last query and the indices of the entries it matched, so a query
+/// that extends it only has to look at those.
+struct Narrowing {
+    query: Query,
+    hits: Vec<usize>,
 }
 
 pub struct Searcher {
-    matcher: SkimMatcherV2,
+    apps: Vec<Arc<AppEntry>>,
+    matcher: Box<dyn Matcher>,
+    options: MatchOptions,
+    typo_fallback_threshold: usize,
+    max_results: usize,
+    empty_query: EmptyQueryConfig,
+    ranking: RankingConfig,
+    explain: bool,
     database: Database,
+    narrowing: Option<Narrowing>,
 }
 
 impl Searcher {
-    pub fn new(database: Database) -> Self {
+    pub fn new(apps: Vec<AppEntry>, database: Database, config: &SearchConfig) -> Self {
         Self {
-            matcher: SkimMatcherV2::default(),
+            apps: apps.into_iter().map(Arc::new).collect(),
+            matcher: config.matcher.build(),
+            options: MatchOptions {
+                case: config.case,
+                normalize: config.normalize,
+                regex: config.matcher == MatcherKind::Regex,
+            },
+            typo_fallback_threshold: config.typo_fallback_threshold,
+            max_results: config.max_results,
+            empty_query: config.empty_query.clone(),
+            ranking: config.ranking.clone(),
+            explain: false,
             database,
+            narrowing: None,
         }
     }
 
-    pub fn search(&self, query: &str, apps: &[AppEntry]) -> Vec<SearchResult> {
+    pub fn set_matcher(&mut self, matcher: MatcherKind) {
+        self.matcher = matcher.build();
+        self.options.regex = matcher == MatcherKind::Regex;
+        self.reset();
+    }
+
+    pub fn set_database(&mut self, database: Database) {
+        self.database = database;
+        self.reset();
+    }
+
+    /// Fills in [`SearchResult::explanation`] for query matches.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain;
+    }
+
+    /// Forgets the previous query, so the next search scans every entry.
+    pub fn reset(&mut self) {
+        self.narrowing = None;
+    }
+
+    pub fn search(&mut self, query: &str) -> Vec<SearchResult> {
+        self.search_until(query, &|| false).unwrap_or_default()
+    }
+
+    /// Like [`search`](Self::search), but gives up and returns `None` once
+    /// `cancelled` returns true, which is checked before scoring each entry.
+    pub fn search_until(&mut self, query: &str, cancelled: &(dyn Fn() -> bool + Sync)) -> Option<Vec<SearchResult>> {
+        self.search_streaming(query, cancelled, &mut |_| {})
+    }
+
+    /// Like [`search_until`](Self::search_until), but passes the best
+    /// results among the entries scored so far to `partial` every
+    /// [`STREAM_CHUNK`] entries, so long searches show results early.
+    pub fn search_streaming(
+        &mut self,
+        query: &str,
+        cancelled: &(dyn Fn() -> bool + Sync),
+        partial: &mut dyn FnMut(Vec<SearchResult>),
+    ) -> Option<Vec<SearchResult>> {
+        let query = Query::parse(query, &self.options);
         if query.is_empty() {
-            return self.get_recent_apps(apps, 20);
+            self.narrowing = None;
+            return Some(self.empty_query_results());
         }
 
-        let mut results: Vec<SearchResult> = apps
+        // While the query is being typed each keystroke usually extends the
+        // previous one, which can only drop matches
+        let candidates = match self.narrowing.take() {
+            Some(previous) if query.refines(&previous.query, self.matcher.as_ref()) => previous.hits,
+            _ => (0..self.apps.len())
+                .filter(|&index| !self.database.is_hidden(&self.apps[index].id))
+                .collect(),
+        };
+
+        let mut results = Vec::new();
+        let mut chunks = candidates.chunks(STREAM_CHUNK).peekable();
+        while let Some(chunk) = chunks.next() {
+            results.extend(self.score_candidates(&query, chunk, cancelled));
+            if cancelled() {
+                return None;
+            }
+            if chunks.peek().is_some() {
+                sort_results(&mut results);
+                let limit = if self.max_results > 0 { self.max_results } else { results.len() };
+                partial(results.iter().take(limit).map(|(_, result)| result.clone()).collect());
+            }
+        }
+        sort_results(&mut results);
+
+        let hits = results.iter().map(|(index, _)| *index).collect();
+        let mut results: Vec<SearchResult> = results.into_iter().map(|(_, result)| result).collect();
+
+        // Only when the query itself found too little, as this looks at
+        // every entry again
+        if results.len() < self.typo_fallback_threshold {
+            if let Some(term) = query.single_fuzzy_term() {
+                let approximate = self.approximate_matches(&term.text, &results, cancelled)?;
+                results.extend(approximate);
+            }
+        }
+
+        if self.max_results > 0 {
+            results.truncate(self.max_results);
+        }
+
+        self.narrowing = Some(Narrowing { query, hits });
+        Some(results)
+    }
+
+    /// Scores the entries at `candidates` against the query, in parallel
+    /// for large lists. Returns each match with its entry's index.
+    fn score_candidates(
+        &self,
+        query: &Query,
+        candidates: &[usize],
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Vec<(usize, SearchResult)> {
+        let score = |&index: &usize| {
+            if cancelled() {
+                return None;
+            }
+            self.score(query, index).map(|result| (index, result))
+        };
+        if candidates.len() >= PARALLEL_THRESHOLD {
+            candidates.par_iter().filter_map(score).collect()
+        } else {
+            candidates.iter().filter_map(score).collect()
+        }
+    }
+
+    fn score(&self, query: &Query, index: usize) -> Option<SearchResult> {
+        let app = &self.apps[index];
+        let fields = self.fields(app);
+        let matched = query.evaluate(&fields, self.matcher.as_ref())?;
+
+        let frecency = self.database.calculate_frecency(&app.id);
+        let pinned = self.database.is_pinned(&app.id);
+        let name_boost = if matched.field == Some(MatchField::Name) { self.ranking.name_boost } else { 0 };
+        let pin_boost = if pinned { self.ranking.pin_boost } else { 0 };
+        let frecency_bonus = (frecency.min(self.ranking.frecency_cap) * self.ranking.frecency_weight) as i64;
+
+        let explanation = self.explain.then(|| Explanation {
+            fields: query.field_scores(&fields, self.matcher.as_ref()),
+            match_score: matched.score,
+            name_boost,
+            pin_boost,
+            frecency_bonus,
+        });
+
+        Some(SearchResult {
+            app: Arc::clone(app),
+            score: matched.score + name_boost + pin_boost + frecency_bonus,
+            frecency,
+            pinned,
+            matched_field: matched.field,
+            match_indices: matched.indices,
+            approximate: false,
+            explanation,
+            group: None,
+        })
+    }
+
+    /// Finds entries whose name, name words or keywords are within a small
+    /// edit distance of `text`, for queries with typos that match nothing.
+    /// Entries already in `found` are skipped. Returns `None` once
+    /// `cancelled` returns true, which is checked before each entry.
+    fn approximate_matches(
+        &self,
+        text: &str,
+        found: &[SearchResult],
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Option<Vec<SearchResult>> {
+        let normalize = |text: &str| -> Vec<char> {
+            Normalized::new(text, true, self.options.normalize).text.chars().collect()
+        };
+        let query = normalize(text);
+        if query.len() < 3 {
+            return Some(Vec::new());
+        }
+        // Allow one edit per four characters, up to three
+        let max_distance = (query.len() / 4).clamp(1, 3);
+
+        let mut results: Vec<(usize, SearchResult)> = self
+            .apps
             .iter()
+            .take_while(|_| !cancelled())
+            .filter(|app| !self.database.is_hidden(&app.id))
+            .filter(|app| !found.iter().any(|result| result.app.id == app.id))
             .filter_map(|app| {
-                let name_score = self.matcher.fuzzy_match(&app.name, query).unwrap_or(0);
-
-                let exec_base = app.exec.split_whitespace().next().unwrap_or("");
-                let exec_name = exec_base.split('/').last().unwrap_or("");
-                let exec_score = self.matcher.fuzzy_match(exec_name, query).unwrap_or(0);
-
-                let comment_score = app
-                    .comment
-                    .as_ref()
-                    .and_then(|c| self.matcher.fuzzy_match(c, query))
-                    .unwrap_or(0);
-
-                let category_score = app
-                    .categories
-                    .iter()
-                    .filter_map(|cat| self.matcher.fuzzy_match(cat, query))
-                    .max()
-                    .unwrap_or(0);
-
-                let base_score = name_score.max(exec_score).max(comment_score / 2).max(category_score / 3);
-
-                if base_score > 0 {
-                    // Check both regular name and path-based entries
-                    let frecency = if app.name.ends_with(" [Path]") {
-                        self.database.calculate_frecency(&format!("path:{}", app.exec))
-                    } else {
-                        self.database.calculate_frecency(&app.name)
-                    };
-                    let boost = if name_score == base_score { 10 } else { 0 };
-                    let final_score = base_score + boost + (frecency.min(100.0) as i64 / 10);
-
-                    Some(SearchResult {
-                        app: app.clone(),
-                        score: final_score,
-                        frecency,
+                let candidates = std::iter::once((MatchField::Name, app.name.as_str()))
+                    .chain(app.name.split_whitespace().map(|word| (MatchField::Name, word)))
+                    .chain(app.keywords.iter().enumerate().map(|(i, kw)| (MatchField::Keyword(i), kw.as_str())));
+
+                let (field, distance) = candidates
+                    .filter_map(|(field, candidate)| {
+                        let candidate = normalize(candidate);
+                        // Compare against the same-length prefix too, so
+                        // partially typed words are found
+                        let prefix = &candidate[..candidate.len().min(query.len())];
+                        let distance = edit_distance(&query, &candidate, max_distance)
+                            .into_iter()
+                            .chain(edit_distance(&query, prefix, max_distance))
+                            .min()?;
+                        Some((field, distance))
                     })
-                } else {
-                    None
-                }
+                    .min_by_key(|(_, distance)| *distance)?;
+
+                let frecency = self.database.calculate_frecency(&app.id);
+                Some((distance, SearchResult {
+                    app: Arc::clone(app),
+                    score: -(distance as i64),
+                    frecency,
+                    pinned: self.database.is_pinned(&app.id),
+                    matched_field: Some(field),
+                    match_indices: Vec::new(),
+                    approximate: true,
+                    explanation: None,
+                    group: None,
+                }))
             })
             .collect();
+        if cancelled() {
+            return None;
+        }
 
-        results.sort_by(|a, b| {
-            b.score
-                .cmp(&a.score)
+        results.sort_by(|(a_distance, a), (b_distance, b)| {
+            a_distance
+                .cmp(b_distance)
                 .then_with(|| b.frecency.partial_cmp(&a.frecency).unwrap_or(std::cmp::Ordering::Equal))
                 .then_with(|| a.app.name.cmp(&b.app.name))
         });
 
-        results
+        Some(results.into_iter().map(|(_, result)| result).collect())
     }
 
-    fn get_recent_apps(&self, apps: &[AppEntry], limit: usize) -> Vec<SearchResult> {
-        let mut results: Vec<SearchResult> = apps
+    /// Returns the searchable fields of `app` with the configured weights
+    /// applied to their scores. Name comes first so it wins ties. The
+    /// desktop file path is included for `file:` filters.
+    fn fields<'a>(&self, app: &'a AppEntry) -> Vec<(MatchField, &'a str, f64)> {
+        let ranking = &self.ranking;
+        let mut fields = vec![
+            (MatchField::Name, app.name.as_str(), ranking.name),
+            (MatchField::Exec, exec_name(app), ranking.exec),
+        ];
+        if let Some(comment) = &app.comment {
+            fields.push((MatchField::Comment, comment.as_str(), ranking.comment));
+        }
+        fields.extend(
+            app.keywords
+                .iter()
+                .enumerate()
+                .map(|(i, kw)| (MatchField::Keyword(i), kw.as_str(), ranking.keyword)),
+        );
+        fields.extend(
+            app.categories
+                .iter()
+                .enumerate()
+                .map(|(i, cat)| (MatchField::Category(i), cat.as_str(), ranking.category)),
+        );
+        fields.push((MatchField::File, MatchField::File.text(app), 1.0));
+        fields
+    }
+
+    /// Lists entries for the empty query according to the configured view.
+    fn empty_query_results(&self) -> Vec<SearchResult> {
+        let mut results: Vec<SearchResult> = self
+            .apps
             .iter()
+            .filter(|app| !self.database.is_hidden(&app.id))
             .map(|app| {
-                // Check both regular name and path-based entries
-                let frecency = if app.name.ends_with(" [Path]") {
-                    self.database.calculate_frecency(&format!("path:{}", app.exec))
-                } else {
-                    self.database.calculate_frecency(&app.name)
-                };
+                let frecency = self.database.calculate_frecency(&app.id);
                 SearchResult {
-                    app: app.clone(),
+                    app: Arc::clone(app),
                     score: frecency as i64,
                     frecency,
+                    pinned: self.database.is_pinned(&app.id),
+                    matched_field: None,
+                    match_indices: Vec::new(),
+                    approximate: false,
+                    explanation: None,
+                    group: None,
                 }
             })
             .collect();
 
-        results.sort_by(|a, b| {
-            b.frecency
-                .partial_cmp(&a.frecency)
-                .unwrap_or(std::cmp::Ordering::Equal)
-                .then_with(|| a.app.name.cmp(&b.app.name))
-        });
+        let pin_position = |result: &SearchResult| self.database.pin_position(&result.app.id).unwrap_or(usize::MAX);
+
+        match self.empty_query.view {
+            EmptyView::Recent => {
+                // Pinned entries come first in pin order, followed by
+                // everything else by frecency, so entries never launched
+                // fill up the list alphabetically
+                results.sort_by(|a, b| {
+                    pin_position(a)
+                        .cmp(&pin_position(b))
+                        .then_with(|| b.frecency.partial_cmp(&a.frecency).unwrap_or(std::cmp::Ordering::Equal))
+                        .then_with(|| a.app.name.cmp(&b.app.name))
+                });
+            }
+            EmptyView::Pinned => {
+                results.retain(|result| result.pinned);
+                results.sort_by_key(pin_position);
+            }
+            EmptyView::All => {
+                results.sort_by_cached_key(|result| result.app.name.to_lowercase());
+            }
+            EmptyView::Categories => {
+                for result in &mut results {
+                    result.group = Some(result.app.main_category().unwrap_or("Other"));
+                }
+                // Entries without a main category go last
+                results.sort_by_cached_key(|result| {
+                    (result.group == Some("Other"), result.group, result.app.name.to_lowercase())
+                });
+            }
+        }
 
-        results.truncate(limit);
+        let default_limit = if self.empty_query.view == EmptyView::Recent { 20 } else { 0 };
+        let limit = self.empty_query.limit.unwrap_or(default_limit);
+        if limit > 0 {
+            results.truncate(limit);
+        }
         results
     }
 }
+
+/// Sorts results best first: by score, then frecency, then name.
+fn sort_results(results: &mut [(usize, SearchResult)]) {
+    let sort = |(_, a): &(usize, SearchResult), (_, b): &(usize, SearchResult)| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| b.frecency.partial_cmp(&a.frecency).unwrap_or(std::cmp::Ordering::Equal))
+            .then_with(|| a.app.name.cmp(&b.app.name))
+    };
+    if results.len() >= PARALLEL_THRESHOLD {
+        results.par_sort_by(sort);
+    } else {
+        results.sort_by(sort);
+    }
+}
+
+/// Optimal string alignment distance: the number of insertions, deletions,
+/// substitutions and transpositions of adjacent characters needed to turn
+/// `a` into `b`. Returns `None` if it exceeds `max`.
+fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev_prev: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (prev[j] + 1).min(current[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(prev_prev[j - 2] + 1);
+            }
+        }
+        // Every later row is at least the minimum of this one
+        if current.iter().min().is_some_and(|&min| min > max) {
+            return None;
+        }
+        prev_prev = std::mem::replace(&mut prev, current);
+    }
+
+    Some(prev[b.len()]).filter(|&distance| distance <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn app(name: &str, comment: &str) -> AppEntry {
+        AppEntry {
+            id: format!("{}.desktop", name.to_lowercase().replace(' ', "-")),
+            name: name.to_string(),
+            exec: name.to_lowercase().replace(' ', "-"),
+            icon: None,
+            comment: Some(comment.to_string()),
+            categories: Vec::new(),
+            keywords: Vec::new(),
+            mime_types: Vec::new(),
+            desktop_file: PathBuf::new(),
+            terminal: false,
+            no_display: false,
+        }
+    }
+
+    fn names(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.app.name.as_str()).collect()
+    }
+
+    fn distance(a: &str, b: &str) -> Option<usize> {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        edit_distance(&a, &b, 3)
+    }
+
+    #[test]
+    fn edit_distance_counts_typos() {
+        assert_eq!(distance("firefox", "firefox"), Some(0));
+        assert_eq!(distance("firfox", "firefox"), Some(1));
+        assert_eq!(distance("thudnerbird", "thunderbird"), Some(1));
+        assert_eq!(distance("firefix", "firefox"), Some(1));
+        assert_eq!(distance("fierfxo", "firefox"), Some(2));
+        assert_eq!(distance("chrome", "firefox"), None);
+    }
+
+    #[test]
+    fn typo_fallback_adds_approximate_matches_until_cancelled() {
+        let apps = vec![app("Firefox", "Web Browser"), app("Thunderbird", "Mail")];
+        let mut searcher = Searcher::new(apps, Database::new(), &SearchConfig::default());
+        let results = searcher.search("thudnerbird");
+        assert_eq!(names(&results), ["Thunderbird"]);
+        assert!(results[0].approximate);
+
+        assert!(searcher.search_until("thudnerbird", &|| true).is_none());
+        assert!(searcher.approximate_matches("thudnerbird", &[], &|| true).is_none());
+    }
+
+    #[test]
+    fn narrowing_matches_full_search() {
+        let apps = vec![
+            app("Firefox", "Web Browser"),
+            app("Files", "Access and organize files"),
+            app("Fire Starter", "Lights fires"),
+            app("LibreOffice Writer", "Create and edit text"),
+            app("Chromium", "Web Browser"),
+        ];
+        let mut incremental = Searcher::new(apps.clone(), Database::new(), &SearchConfig::default());
+        let mut full = Searcher::new(apps, Database::new(), &SearchConfig::default());
+
+        for query in ["f", "fi", "fir", "fire", "fire s", "fire st", "fire st |", "fire st | w"] {
+            let narrowed = incremental.search(query);
+            full.reset();
+            let expected = full.search(query);
+            assert_eq!(names(&narrowed), names(&expected), "query {:?}", query);
+        }
+
+        // Skim scores aren't monotonic: a long gap can score "qw" at or below
+        // zero while "qwe" scores above it
+        let comment = format!("q{}we", "a".repeat(43));
+        let apps = vec![app("Quartz", &comment)];
+        let mut incremental = Searcher::new(apps.clone(), Database::new(), &SearchConfig::default());
+        let mut full = Searcher::new(apps, Database::new(), &SearchConfig::default());
+        for query in ["q", "qw", "qwe"] {
+            let narrowed = incremental.search(query);
+            full.reset();
+            let expected = full.search(query);
+            assert_eq!(names(&narrowed), names(&expected), "query {:?}", query);
+        }
+        assert_eq!(names(&incremental.search("qwe")), ["Quartz"]);
+    }
+
+    #[test]
+    fn streams_partial_results() {
+        let apps: Vec<AppEntry> = (0..2 * STREAM_CHUNK + 1).map(|i| app(&format!("App {}", i), "")).collect();
+        let config = SearchConfig { max_results: 5, ..SearchConfig::default() };
+        let mut searcher = Searcher::new(apps, Database::new(), &config);
+
+        let mut partials = Vec::new();
+        let results = searcher.search_streaming("app 1", &|| false, &mut |results| partials.push(results)).unwrap();
+        assert_eq!(partials.len(), 2);
+        assert!(partials.iter().all(|partial| !partial.is_empty() && partial.len() <= 5));
+
+        searcher.reset();
+        assert_eq!(names(&results), names(&searcher.search("app 1")));
+    }
+
+    #[test]
+    fn ranking_weights_and_explanation() {
+        let apps = vec![app("Firefox", "Web Browser"), app("Chromium", "Browse the web")];
+        let mut config = SearchConfig::default();
+        config.ranking.comment = 0.0;
+        let mut searcher = Searcher::new(apps.clone(), Database::new(), &config);
+        assert!(searcher.search("browse").is_empty());
+
+        let mut searcher = Searcher::new(apps, Database::new(), &SearchConfig::default());
+        searcher.set_explain(true);
+        let results = searcher.search("fire");
+        let explanation = results[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.fields[0].0, MatchField::Name);
+        assert_eq!(explanation.name_boost, 10);
+        assert_eq!(
+            results[0].score,
+            explanation.match_score + explanation.name_boost + explanation.pin_boost + explanation.frecency_bonus
+        );
+    }
+
+    #[test]
+    fn empty_query_views_and_limits() {
+        let mut apps = vec![
+            app("Files", "Access and organize files"),
+            app("Firefox", "Web Browser"),
+            app("calculator", "Calculate"),
+            app("Firewall", "Configure firewall"),
+        ];
+        apps[1].categories = vec!["GTK".to_string(), "Network".to_string()];
+        apps[2].categories = vec!["Utility".to_string()];
+
+        let mut database = Database::new();
+        database.set_pinned("firewall.desktop", true);
+        database.merge_usage("firefox.desktop", &crate::database::AppUsage {
+            launch_count: 3,
+            last_launched: Some(chrono::Utc::now()),
+        });
+
+        let search = |configure: &dyn Fn(&mut SearchConfig), query: &str| {
+            let mut config = SearchConfig::default();
+            configure(&mut config);
+            let mut searcher = Searcher::new(apps.clone(), database.clone(), &config);
+            searcher
+                .search(query)
+                .iter()
+                .map(|result| (result.group, result.app.name.clone()))
+                .collect::<Vec<_>>()
+        };
+        let names = |results: Vec<(Option<&str>, String)>| -> Vec<String> {
+            results.into_iter().map(|(_, name)| name).collect()
+        };
+
+        assert_eq!(names(search(&|_| {}, "")), ["Firewall", "Firefox", "Files", "calculator"]);
+        assert_eq!(names(search(&|config| config.empty_query.limit = Some(2), "")), ["Firewall", "Firefox"]);
+        assert_eq!(names(search(&|config| config.empty_query.view = EmptyView::Pinned, "")), ["Firewall"]);
+        assert_eq!(
+            names(search(&|config| config.empty_query.view = EmptyView::All, "")),
+            ["calculator", "Files", "Firefox", "Firewall"]
+        );
+        assert_eq!(
+            search(&|config| config.empty_query.view = EmptyView::Categories, ""),
+            [
+                (Some("Network"), "Firefox".to_string()),
+                (Some("Utility"), "calculator".to_string()),
+                (Some("Other"), "Files".to_string()),
+                (Some("Other"), "Firewall".to_string()),
+            ]
+        );
+        assert_eq!(
+            names(search(
+                &|config| {
+                    config.empty_query.view = EmptyView::All;
+                    config.empty_query.limit = Some(2);
+                },
+                ""
+            )),
+            ["calculator", "Files"]
+        );
+        assert_eq!(search(&|config| config.max_results = 2, "fi").len(), 2);
+        assert_eq!(search(&|_| {}, "fi").len(), 3);
+    }
+}
@@ -1,5 +1,12 @@
-use crate::path_completion::PathCompletion;
-use crate::search::SearchResult;
+use crate::config::Theme;
+use crate::editor::Editor;
+use crate::input::InputLine;
+use crate::keymap::KeyMode;
+use crate::matcher::MatcherKind;
+use crate::open_with::{HandlerMatch, OpenWith};
+use crate::path_completion::{PathCompletion, PathFilter};
+use crate::query_history::HistoryMatch;
+use crate::search::{Explanation, MatchField, SearchResult};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
@@ -11,27 +18,72 @@ use ratatui::{
 pub enum SearchMode {
     Applications(Vec<SearchResult>),
     Paths(Vec<PathCompletion>),
+    /// Past queries, while searching the query history
+    History(Vec<HistoryMatch>),
+    /// Applications to open a file with, while choosing one
+    OpenWith(Vec<HandlerMatch>),
 }
 
 pub struct AppState {
-    pub query: String,
-    pub cursor_position: usize,
+    pub input: InputLine,
+    pub editor: Editor,
     pub selected_index: usize,
     pub scroll_offset: usize,
     pub mode: SearchMode,
+    pub matcher: MatcherKind,
+    /// Results for the current query are still being computed
+    pub searching: bool,
+    /// Show how each result's score is made up
+    pub explain: bool,
+    /// Keys typed so far of an unfinished key sequence
+    pub pending_keys: Option<String>,
+    /// Key that cycles the matcher, shown next to its name if bound
+    pub matcher_key: Option<String>,
+    /// Vi mode's current mode; `None` without vi mode
+    pub vi_mode: Option<KeyMode>,
+    /// While searching the query history, the query typed before
+    pub history_search: Option<String>,
+    /// Which entries path mode lists
+    pub path_filter: PathFilter,
+    /// Path mode lists hidden entries without a leading dot being typed
+    pub show_hidden: bool,
+    /// While choosing an application to open a file with, the file and
+    /// its candidates
+    pub open_with: Option<OpenWith>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(matcher: MatcherKind) -> Self {
         Self {
-            query: String::new(),
-            cursor_position: 0,
+            input: InputLine::default(),
+            editor: Editor::default(),
             selected_index: 0,
             scroll_offset: 0,
             mode: SearchMode::Applications(Vec::new()),
+            matcher,
+            searching: false,
+            explain: false,
+            pending_keys: None,
+            matcher_key: None,
+            vi_mode: None,
+            history_search: None,
+            path_filter: PathFilter::default(),
+            show_hidden: false,
+            open_with: None,
         }
     }
 
+    /// Whether the list shows past queries or applications to open a file
+    /// with, which the results of searches mustn't replace.
+    pub fn is_choosing(&self) -> bool {
+        self.history_search.is_some() || self.open_with.is_some()
+    }
+
+    /// The mode key presses are resolved in.
+    pub fn key_mode(&self) -> KeyMode {
+        self.vi_mode.unwrap_or(KeyMode::Insert)
+    }
+
     pub fn move_selection_up(&mut self) {
         if self.selected_index > 0 {
             self.selected_index -= 1;
@@ -44,10 +96,7 @@ impl AppState {
     }
 
     pub fn move_selection_down(&mut self) {
-        let max_index = match &self.mode {
-            SearchMode::Applications(results) => results.len(),
-            SearchMode::Paths(completions) => completions.len(),
-        };
+        let max_index = self.results_count();
 
         if max_index > 0 && self.selected_index < max_index - 1 {
             self.selected_index += 1;
@@ -68,16 +117,21 @@ impl AppState {
     }
 
     pub fn move_selection_page_down(&mut self, page_size: usize) {
-        let max_index = match &self.mode {
-            SearchMode::Applications(results) => results.len(),
-            SearchMode::Paths(completions) => completions.len(),
-        };
+        let max_index = self.results_count();
 
         if max_index > 0 {
             self.selected_index = (self.selected_index + page_size).min(max_index - 1);
         }
     }
 
+    pub fn move_selection_first(&mut self) {
+        self.selected_index = 0;
+    }
+
+    pub fn move_selection_last(&mut self) {
+        self.selected_index = self.results_count().saturating_sub(1);
+    }
+
     pub fn adjust_scroll(&mut self, visible_height: usize) {
         // Keep selection within visible window
         if self.selected_index >= self.scroll_offset + visible_height {
@@ -92,6 +146,12 @@ impl AppState {
         self.scroll_offset = 0;
     }
 
+    /// Keeps the selection in range after the result list shrank.
+    pub fn clamp_selection(&mut self, visible_height: usize) {
+        self.selected_index = self.selected_index.min(self.results_count().saturating_sub(1));
+        self.adjust_scroll(visible_height);
+    }
+
     pub fn get_selected_app(&self) -> Option<&SearchResult> {
         match &self.mode {
             SearchMode::Applications(results) => results.get(self.selected_index),
@@ -106,38 +166,74 @@ impl AppState {
         }
     }
 
+    pub fn get_selected_history(&self) -> Option<&HistoryMatch> {
+        match &self.mode {
+            SearchMode::History(matches) => matches.get(self.selected_index),
+            _ => None,
+        }
+    }
+
+    pub fn get_selected_handler(&self) -> Option<&HandlerMatch> {
+        match &self.mode {
+            SearchMode::OpenWith(handlers) => handlers.get(self.selected_index),
+            _ => None,
+        }
+    }
+
     pub fn results_count(&self) -> usize {
         match &self.mode {
             SearchMode::Applications(results) => results.len(),
             SearchMode::Paths(completions) => completions.len(),
+            SearchMode::History(matches) => matches.len(),
+            SearchMode::OpenWith(handlers) => handlers.len(),
         }
     }
 }
 
-pub fn render(frame: &mut Frame, state: &AppState) {
+pub fn render(frame: &mut Frame, state: &mut AppState, theme: &Theme) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Length(3), Constraint::Min(0)])
         .split(frame.area());
 
     render_search_box(frame, chunks[0], state);
-    render_results(frame, chunks[1], state);
+    render_results(frame, chunks[1], state, theme);
 }
 
-fn render_search_box(frame: &mut Frame, area: Rect, state: &AppState) {
-    let input = Paragraph::new(state.query.as_str())
+fn render_search_box(frame: &mut Frame, area: Rect, state: &mut AppState) {
+    // Inside the borders
+    let width = area.width.saturating_sub(2) as usize;
+    let (visible, cursor_column) = state.input.view(width);
+    let mut title = if let Some(open_with) = &state.open_with {
+        let name = open_with.file.file_name().unwrap_or_default().to_string_lossy();
+        format!(" Open {} with ", name)
+    } else if state.history_search.is_some() {
+        " Search history ".to_string()
+    } else {
+        " Search ".to_string()
+    };
+    match state.vi_mode {
+        Some(KeyMode::Insert) => title.push_str("[INSERT] "),
+        Some(KeyMode::Normal) => title.push_str("[NORMAL] "),
+        None => {}
+    }
+    if let Some(keys) = &state.pending_keys {
+        title.push_str(&format!("({}…) ", keys));
+    }
+
+    let input = Paragraph::new(visible)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Cyan))
-                .title(" Search "),
+                .title(title),
         )
         .style(Style::default().fg(Color::White));
 
     frame.render_widget(input, area);
 
     frame.set_cursor_position((
-        area.x + 1 + state.cursor_position as u16,
+        area.x + 1 + cursor_column as u16,
         area.y + 1,
     ));
 }
@@ -152,7 +248,62 @@ fn calculate_scroll_offset(selected_index: usize, current_offset: usize, visible
     }
 }
 
-fn render_results(frame: &mut Frame, area: Rect, state: &AppState) {
+/// Splits `text` into spans, patching `highlight` over `base` for the
+/// characters at the given char `indices`.
+fn highlight_spans<'a>(text: &'a str, indices: &[usize], base: Style, highlight: Style) -> Vec<Span<'a>> {
+    let highlighted = base.patch(highlight);
+    let mut spans = Vec::new();
+    let mut run_start = 0;
+    let mut run_highlighted = false;
+
+    for (char_index, (byte_index, _)) in text.char_indices().enumerate() {
+        let is_match = indices.contains(&char_index);
+        if is_match != run_highlighted {
+            if byte_index > run_start {
+                let style = if run_highlighted { highlighted } else { base };
+                spans.push(Span::styled(&text[run_start..byte_index], style));
+            }
+            run_start = byte_index;
+            run_highlighted = is_match;
+        }
+    }
+
+    if run_start < text.len() {
+        let style = if run_highlighted { highlighted } else { base };
+        spans.push(Span::styled(&text[run_start..], style));
+    }
+
+    spans
+}
+
+/// Formats a score breakdown such as `63 = 48 (name 48, comment 12) + name
+/// 10 + frecency 5`, leaving out boosts that don't apply.
+fn explain_text(score: i64, explanation: &Explanation) -> String {
+    let fields: Vec<String> = explanation
+        .fields
+        .iter()
+        .map(|(field, score)| format!("{} {}", field.label(), score))
+        .collect();
+    let mut text = format!("{} = {}", score, explanation.match_score);
+    if !fields.is_empty() {
+        text.push_str(&format!(" ({})", fields.join(", ")));
+    }
+
+    for (label, value) in [
+        ("name", explanation.name_boost),
+        ("pin", explanation.pin_boost),
+        ("frecency", explanation.frecency_bonus),
+    ] {
+        if value != 0 {
+            text.push_str(&format!(" + {} {}", label, value));
+        }
+    }
+    text
+}
+
+fn render_results(frame: &mut Frame, area: Rect, state: &AppState, theme: &Theme) {
+    let highlight = theme.match_highlight.to_style();
+
     // Calculate visible height (subtract 2 for borders)
     let visible_height = area.height.saturating_sub(2) as usize;
 
@@ -161,6 +312,13 @@ fn render_results(frame: &mut Frame, area: Rect, state: &AppState) {
 
     let items: Vec<ListItem> = match &state.mode {
         SearchMode::Applications(results) => {
+            // Grouped lists get a column with each group's heading
+            let group_width = results
+                .iter()
+                .filter_map(|result| result.group)
+                .map(|group| group.chars().count())
+                .max();
+
             results
                 .iter()
                 .enumerate()
@@ -169,33 +327,72 @@ fn render_results(frame: &mut Frame, area: Rect, state: &AppState) {
                 .map(|(i, result)| {
                     let is_selected = i == state.selected_index;
 
-                    let mut spans = vec![
-                        Span::styled(
-                            &result.app.name,
-                            if is_selected {
-                                Style::default()
-                                    .fg(Color::Yellow)
-                                    .add_modifier(Modifier::BOLD)
-                            } else {
-                                Style::default().fg(Color::White)
-                            },
-                        ),
-                    ];
+                    let mut spans = Vec::new();
 
-                    if let Some(comment) = &result.app.comment {
+                    if let Some(width) = group_width {
+                        let starts_group = i == scroll_offset || results[i - 1].group != result.group;
+                        let heading = if starts_group { result.group.unwrap_or("") } else { "" };
                         spans.push(Span::styled(
-                            format!(" - {}", comment),
-                            Style::default().fg(Color::Gray),
+                            format!("{:<width$}  ", heading, width = width),
+                            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
                         ));
                     }
 
-                    if result.frecency > 0.0 {
+                    if result.pinned {
+                        spans.push(Span::styled("📌 ", Style::default().fg(Color::Red)));
+                    }
+
+                    if result.approximate {
+                        spans.push(Span::styled("≈ ", Style::default().fg(Color::Magenta)));
+                    }
+
+                    let name_style = if is_selected {
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    let name_indices: &[usize] = if result.matched_field == Some(MatchField::Name) {
+                        &result.match_indices
+                    } else {
+                        &[]
+                    };
+                    spans.extend(highlight_spans(&result.app.name, name_indices, name_style, highlight));
+
+                    if let Some(comment) = &result.app.comment {
+                        let comment_indices: &[usize] = if result.matched_field == Some(MatchField::Comment) {
+                            &result.match_indices
+                        } else {
+                            &[]
+                        };
+                        let comment_style = Style::default().fg(Color::Gray);
+                        spans.push(Span::styled(" - ", comment_style));
+                        spans.extend(highlight_spans(comment, comment_indices, comment_style, highlight));
+                    }
+
+                    // Fields that aren't otherwise shown are appended so it's
+                    // visible why the entry matched
+                    if let Some(field @ (MatchField::Exec | MatchField::Keyword(_) | MatchField::Category(_))) = result.matched_field {
+                        let hint_style = Style::default().fg(Color::DarkGray);
+                        spans.push(Span::styled(" · ", hint_style));
+                        spans.extend(highlight_spans(field.text(&result.app), &result.match_indices, hint_style, highlight));
+                    }
+
+                    if result.frecency > 0.0 && result.group.is_none() {
                         spans.push(Span::styled(
                             format!(" [{}]", result.app.categories.first().unwrap_or(&String::new())),
                             Style::default().fg(Color::DarkGray),
                         ));
                     }
 
+                    if let Some(explanation) = result.explanation.as_ref().filter(|_| state.explain) {
+                        spans.push(Span::styled(
+                            format!("  {}", explain_text(result.score, explanation)),
+                            Style::default().fg(Color::Magenta),
+                        ));
+                    }
+
                     ListItem::new(Line::from(spans))
                 })
                 .collect()
@@ -211,21 +408,26 @@ fn render_results(frame: &mut Frame, area: Rect, state: &AppState) {
 
                     let mut spans = vec![
                         Span::styled(
-                            if completion.is_dir { "📁 " } else { "🔧 " },
-                            Style::default().fg(Color::Cyan),
-                        ),
-                        Span::styled(
-                            &completion.display_name,
-                            if is_selected {
-                                Style::default()
-                                    .fg(Color::Yellow)
-                                    .add_modifier(Modifier::BOLD)
+                            if completion.is_dir {
+                                "📁 "
+                            } else if completion.is_executable {
+                                "🔧 "
                             } else {
-                                Style::default().fg(Color::White)
+                                "📄 "
                             },
+                            Style::default().fg(Color::Cyan),
                         ),
                     ];
 
+                    let name_style = if is_selected {
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    spans.extend(highlight_spans(&completion.display_name, &completion.match_indices, name_style, highlight));
+
                     if completion.is_dir {
                         spans.push(Span::styled(
                             "/",
@@ -237,28 +439,86 @@ fn render_results(frame: &mut Frame, area: Rect, state: &AppState) {
                 })
                 .collect()
         },
+        SearchMode::History(matches) => {
+            matches
+                .iter()
+                .enumerate()
+                .skip(scroll_offset)
+                .take(visible_height)
+                .map(|(i, found)| {
+                    let query_style = if i == state.selected_index {
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    ListItem::new(Line::from(highlight_spans(&found.query, &found.match_indices, query_style, highlight)))
+                })
+                .collect()
+        },
+        SearchMode::OpenWith(handlers) => {
+            handlers
+                .iter()
+                .enumerate()
+                .skip(scroll_offset)
+                .take(visible_height)
+                .map(|(i, found)| {
+                    let name_style = if i == state.selected_index {
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    let mut spans = highlight_spans(&found.app.name, &found.match_indices, name_style, highlight);
+                    if let Some(comment) = &found.app.comment {
+                        spans.push(Span::styled(format!(" - {}", comment), Style::default().fg(Color::Gray)));
+                    }
+                    ListItem::new(Line::from(spans))
+                })
+                .collect()
+        },
     };
 
-    let title = match &state.mode {
+    let mut title = match &state.mode {
         SearchMode::Applications(_) => {
+            let matcher = match &state.matcher_key {
+                Some(key) => format!("{} {}", state.matcher.name(), key),
+                None => state.matcher.name().to_string(),
+            };
             if state.results_count() > visible_height {
-                format!(" Applications ({}/{}) ",
+                format!(" Applications ({}/{}) [{}] ",
                     state.selected_index + 1,
-                    state.results_count())
+                    state.results_count(),
+                    matcher)
             } else {
-                format!(" Applications ({}) ", state.results_count())
+                format!(" Applications ({}) [{}] ", state.results_count(), matcher)
             }
         },
         SearchMode::Paths(_) => {
+            let mut filter = state.path_filter.name().to_string();
+            if state.show_hidden {
+                filter.push_str(", hidden");
+            }
             if state.results_count() > visible_height {
-                format!(" Path Completions ({}/{}) ",
+                format!(" Path Completions ({}/{}) [{}] ",
                     state.selected_index + 1,
-                    state.results_count())
+                    state.results_count(),
+                    filter)
             } else {
-                format!(" Path Completions ({}) ", state.results_count())
+                format!(" Path Completions ({}) [{}] ", state.results_count(), filter)
             }
         }
+        SearchMode::History(_) => format!(" Query History ({}) ", state.results_count()),
+        SearchMode::OpenWith(_) => {
+            let mime = state.open_with.as_ref().map_or("", |open_with| open_with.mime.as_str());
+            format!(" Applications for {} ({}) ", mime, state.results_count())
+        }
     };
+    if state.searching {
+        title.push_str("searching… ");
+    }
 
     let list = List::new(items)
         .block(
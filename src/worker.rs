@@ -0,0 +1,227 @@
+use crate::path_completion::PathCompleter;
+use crate::search::Searcher;
+use crate::ui::SearchMode;
+use anyhow::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Results for the request numbered `generation`.
+struct Update {
+    generation: u64,
+    mode: SearchMode,
+    /// Whether these are the final results rather than the best so far
+    complete: bool,
+}
+
+/// A change to the searcher, applied by the next search before it starts.
+type SearcherUpdate = Box<dyn FnOnce(&mut Searcher) + Send>;
+
+/// Runs searches and path completions on tokio's blocking pool, so the UI
+/// thread never waits on a large entry list or a slow directory. Only the
+/// latest request matters: a new request cancels the one in flight and
+/// results of stale requests are dropped. Searches over many entries
+/// deliver the best results so far while they run.
+pub struct SearchWorker {
+    runtime: Option<Runtime>,
+    searcher: Arc<Mutex<Searcher>>,
+    /// Changes waiting for the searcher, which a running search holds
+    updates: Arc<Mutex<Vec<SearcherUpdate>>>,
+    path_completer: PathCompleter,
+    /// Number of the latest request
+    generation: Arc<AtomicU64>,
+    /// Number of the request whose results were delivered last
+    received: u64,
+    sender: UnboundedSender<Update>,
+    receiver: UnboundedReceiver<Update>,
+}
+
+impl SearchWorker {
+    pub fn new(searcher: Searcher, path_completer: PathCompleter) -> Result<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Ok(Self {
+            runtime: Some(Runtime::new()?),
+            searcher: Arc::new(Mutex::new(searcher)),
+            updates: Arc::new(Mutex::new(Vec::new())),
+            path_completer,
+            generation: Arc::new(AtomicU64::new(0)),
+            received: 0,
+            sender,
+            receiver,
+        })
+    }
+
+    /// Starts searching for `query`, as applications or as a path depending
+    /// on its form.
+    pub fn request(&mut self, query: &str) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let latest = Arc::clone(&self.generation);
+        let searcher = Arc::clone(&self.searcher);
+        let updates = Arc::clone(&self.updates);
+        let path_completer = self.path_completer;
+        let sender = self.sender.clone();
+        let query = query.to_string();
+
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+        runtime.spawn_blocking(move || {
+            let is_stale = || latest.load(Ordering::SeqCst) != generation;
+            if is_stale() {
+                return;
+            }
+
+            // The UI may have exited already, so sending can fail
+            let mode = if PathCompleter::is_path_query(&query) {
+                SearchMode::Paths(path_completer.complete_path(&query))
+            } else {
+                let mut searcher = searcher.lock().unwrap_or_else(|e| e.into_inner());
+                let pending = std::mem::take(&mut *updates.lock().unwrap_or_else(|e| e.into_inner()));
+                for update in pending {
+                    update(&mut searcher);
+                }
+                let mut partial = |results| {
+                    let mode = SearchMode::Applications(results);
+                    let _ = sender.send(Update { generation, mode, complete: false });
+                };
+                match searcher.search_streaming(&query, &is_stale, &mut partial) {
+                    Some(results) => SearchMode::Applications(results),
+                    None => return,
+                }
+            };
+
+            let _ = sender.send(Update { generation, mode, complete: true });
+        });
+    }
+
+    /// Whether results for the latest request are still outstanding.
+    pub fn is_searching(&self) -> bool {
+        self.received != self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns the results of the latest request if they have arrived, or
+    /// the best ones so far while it runs.
+    pub fn try_recv(&mut self) -> Option<SearchMode> {
+        let mut latest = None;
+        while let Ok(update) = self.receiver.try_recv() {
+            if let Some(mode) = self.accept(update) {
+                latest = Some(mode);
+            }
+        }
+        latest
+    }
+
+    /// Blocks until the results of the latest request arrive, so actions on
+    /// the selection never act on results for an older query. Returns
+    /// `None` if no request is outstanding.
+    pub fn wait(&mut self) -> Option<SearchMode> {
+        while self.is_searching() {
+            let update = self.receiver.blocking_recv()?;
+            if let Some(mode) = self.accept(update) {
+                if !self.is_searching() {
+                    return Some(mode);
+                }
+            }
+        }
+        None
+    }
+
+    fn accept(&mut self, update: Update) -> Option<SearchMode> {
+        if update.generation != self.generation.load(Ordering::SeqCst) {
+            return None;
+        }
+        if update.complete {
+            self.received = update.generation;
+        }
+        Some(update.mode)
+    }
+
+    /// Changes the searcher, cancelling the search in flight. The change is
+    /// queued rather than waiting for that search to let go of the
+    /// searcher; follow with a new [`request`](Self::request) to apply it
+    /// and get updated results.
+    pub fn update_searcher(&mut self, update: impl FnOnce(&mut Searcher) + Send + 'static) {
+        self.received = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.updates.lock().unwrap_or_else(|e| e.into_inner()).push(Box::new(update));
+    }
+
+    /// Changes how paths are completed, from the next request on.
+    pub fn update_path_completer(&mut self, update: impl FnOnce(&mut PathCompleter)) {
+        update(&mut self.path_completer);
+    }
+}
+
+impl Drop for SearchWorker {
+    fn drop(&mut self) {
+        // Cancel the search in flight and don't wait for a directory read
+        // that may hang
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchConfig;
+    use crate::database::Database;
+    use crate::desktop_entry::AppEntry;
+    use crate::matcher::MatcherKind;
+    use std::path::PathBuf;
+
+    fn app(name: &str) -> AppEntry {
+        AppEntry {
+            id: format!("{}.desktop", name.to_lowercase()),
+            name: name.to_string(),
+            exec: name.to_lowercase(),
+            icon: None,
+            comment: None,
+            categories: Vec::new(),
+            keywords: Vec::new(),
+            mime_types: Vec::new(),
+            desktop_file: PathBuf::new(),
+            terminal: false,
+            no_display: false,
+        }
+    }
+
+    #[test]
+    fn delivers_only_the_latest_results() {
+        let apps = vec![app("Firefox"), app("Files"), app("Chromium")];
+        let searcher = Searcher::new(apps, Database::new(), &SearchConfig::default());
+        let mut worker = SearchWorker::new(searcher, PathCompleter::default()).unwrap();
+
+        worker.request("fi");
+        worker.request("chr");
+        assert!(worker.is_searching());
+
+        let Some(SearchMode::Applications(results)) = worker.wait() else {
+            panic!("expected application results");
+        };
+        let names: Vec<&str> = results.iter().map(|result| result.app.name.as_str()).collect();
+        assert_eq!(names, ["Chromium"]);
+        assert!(!worker.is_searching());
+        assert!(worker.try_recv().is_none());
+    }
+
+    #[test]
+    fn queues_searcher_updates_while_searching() {
+        let searcher = Searcher::new(vec![app("Firefox")], Database::new(), &SearchConfig::default());
+        let mut worker = SearchWorker::new(searcher, PathCompleter::default()).unwrap();
+
+        // Stands in for a long search holding the searcher
+        let searcher = Arc::clone(&worker.searcher);
+        let held = searcher.lock().unwrap();
+        worker.update_searcher(|searcher| searcher.set_matcher(MatcherKind::Prefix));
+        drop(held);
+
+        worker.request("refox");
+        let Some(SearchMode::Applications(results)) = worker.wait() else {
+            panic!("expected application results");
+        };
+        assert!(results.is_empty());
+    }
+}